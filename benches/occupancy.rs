@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_game::bench_support::{
+    update_occupancy, Block, Direction, GlobalPlayerState, LevelRules, LevelWalls, Movable,
+    MoveRules, Occupancy, Player, Pullable, Pushable, Tile, WorldWalls,
+};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const LEVEL_IID: &str = "bench";
+const BLOCKS_PER_PLAYER: usize = 10;
+const DIRECTIONS: [Direction; 4] = [
+    Direction::North,
+    Direction::East,
+    Direction::South,
+    Direction::West,
+];
+
+/// Blocks on every other cell of every other row, and a player in the free
+/// row above every few of them, so each block has room to step aside.
+struct Layout {
+    blocks: Vec<GridCoords>,
+    players: Vec<GridCoords>,
+    width: i32,
+    height: i32,
+}
+
+impl Layout {
+    fn new(count: usize) -> Layout {
+        let per_row = ((count as f32).sqrt().ceil() as i32).max(1);
+        let cell = |index: usize| {
+            let index = index as i32;
+            GridCoords::new(1 + index % per_row * 2, 1 + index / per_row * 2)
+        };
+        let blocks: Vec<GridCoords> = (0..count).map(cell).collect();
+        let players = (0..count)
+            .step_by(BLOCKS_PER_PLAYER)
+            .map(|index| cell(index) + GridCoords::new(0, 1))
+            .collect();
+        Layout {
+            blocks,
+            players,
+            width: per_row * 2 + 2,
+            height: (count as i32 / per_row + 1) * 2 + 2,
+        }
+    }
+}
+
+// every player asks the movement rules about every direction, as input would
+fn check_moves(move_rules: MoveRules, player_query: Query<Entity, With<Player>>) {
+    for entity in player_query.iter() {
+        for direction in DIRECTIONS {
+            black_box(move_rules.check(entity, direction).is_ok());
+        }
+    }
+}
+
+// the occupancy index and the movement rules as the game runs them, on a
+// walled level spawned the way bevy_ecs_ldtk does: movables are children of
+// the level entity
+fn level_app(layout: &Layout) -> (App, Vec<Entity>) {
+    let mut level_walls = LevelWalls::new(GridCoords::new(0, 0), layout.width, layout.height);
+    for x in 0..layout.width {
+        level_walls.set_tile(&GridCoords::new(x, 0), Tile::Wall);
+        level_walls.set_tile(&GridCoords::new(x, layout.height - 1), Tile::Wall);
+    }
+    for y in 0..layout.height {
+        level_walls.set_tile(&GridCoords::new(0, y), Tile::Wall);
+        level_walls.set_tile(&GridCoords::new(layout.width - 1, y), Tile::Wall);
+    }
+    let mut world_walls = WorldWalls::default();
+    world_walls.insert(LEVEL_IID.to_string(), level_walls);
+
+    let mut app = App::new();
+    app.add_event::<LevelEvent>()
+        .init_resource::<Occupancy>()
+        .init_resource::<GlobalPlayerState>()
+        .init_resource::<LevelRules>()
+        .insert_resource(world_walls)
+        .add_systems(
+            Update,
+            (update_occupancy, check_moves.after(update_occupancy)),
+        );
+
+    let mut blocks = Vec::new();
+    app.world
+        .spawn(LevelIid::new(LEVEL_IID))
+        .with_children(|level| {
+            for grid_coords in layout.blocks.iter() {
+                blocks.push(
+                    level
+                        .spawn((Movable, Block, Pushable, Pullable, *grid_coords))
+                        .id(),
+                );
+            }
+            for grid_coords in layout.players.iter() {
+                level.spawn((Movable, Player::default(), *grid_coords));
+            }
+        });
+    app.update();
+    let occupancy = app.world.resource::<Occupancy>();
+    assert!(blocks
+        .iter()
+        .all(|block| occupancy.position(*block).is_some()));
+    (app, blocks)
+}
+
+// frame times for the index and the rules together, at growing block counts
+fn occupancy_frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("occupancy_frames");
+    for count in [100, 500, 2000] {
+        let layout = Layout::new(count);
+        // one block steps aside and back each frame, the rest of the index
+        // is left alone while every player checks its moves
+        let (mut app, blocks) = level_app(&layout);
+        let mut step = 0;
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| {
+                let entity = blocks[step % blocks.len()];
+                let offset = if step / blocks.len() % 2 == 0 { 1 } else { -1 };
+                step += 1;
+                if let Some(mut grid_coords) = app.world.get_mut::<GridCoords>(entity) {
+                    grid_coords.x += offset;
+                }
+                app.update();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, occupancy_frames);
+criterion_main!(benches);
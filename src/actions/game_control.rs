@@ -5,25 +5,41 @@ pub enum GameControl {
     Down,
     Left,
     Right,
+    Grab,
+    Undo,
+    Restart,
+    Hint,
+    UndoPastDeadlock,
 }
 
 impl GameControl {
-    pub fn pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+    fn keys(&self) -> &'static [KeyCode] {
         match self {
-            GameControl::Up => {
-                keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right)
-            }
+            GameControl::Up => &[KeyCode::W, KeyCode::Up],
+            GameControl::Down => &[KeyCode::S, KeyCode::Down],
+            GameControl::Left => &[KeyCode::A, KeyCode::Left],
+            GameControl::Right => &[KeyCode::D, KeyCode::Right],
+            GameControl::Grab => &[KeyCode::Space],
+            GameControl::Undo => &[KeyCode::Z],
+            GameControl::Restart => &[KeyCode::R],
+            GameControl::Hint => &[KeyCode::H],
+            GameControl::UndoPastDeadlock => &[KeyCode::U],
         }
     }
+
+    pub fn pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+        keyboard_input.any_pressed(self.keys().iter().copied())
+    }
+
+    pub fn just_pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+        keyboard_input.any_just_pressed(self.keys().iter().copied())
+    }
+
+    pub fn just_released(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+        self.keys()
+            .iter()
+            .any(|key| keyboard_input.just_released(*key))
+    }
 }
 
 pub fn get_movement(control: GameControl, input: &Res<Input<KeyCode>>) -> f32 {
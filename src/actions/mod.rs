@@ -2,7 +2,12 @@ use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 
 use crate::actions::game_control::{get_movement, GameControl};
-use crate::player::Player;
+use crate::history::{restart_from_input, undo_from_input};
+use crate::player::{
+    grab_from_held_input, handle_move_player, turn_player_from_input, ungrab_from_release_input,
+    Direction, Player,
+};
+use crate::replay::ReplayPlayback;
 use crate::GameState;
 
 mod game_control;
@@ -13,11 +18,20 @@ pub struct ActionsPlugin;
 
 // This plugin listens for keyboard input and converts the input into Actions
 // Actions can then be used as a resource in other systems to act on the player input.
+// While a replay is playing back, the replay fills in the Actions instead.
 impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Actions>().add_systems(
             Update,
-            set_movement_actions.run_if(in_state(GameState::Playing)),
+            set_movement_actions
+                .before(handle_move_player)
+                .before(turn_player_from_input)
+                .before(grab_from_held_input)
+                .before(ungrab_from_release_input)
+                .before(undo_from_input)
+                .before(restart_from_input)
+                .run_if(in_state(GameState::Playing))
+                .run_if(not(resource_exists::<ReplayPlayback>())),
         );
     }
 }
@@ -25,6 +39,26 @@ impl Plugin for ActionsPlugin {
 #[derive(Default, Resource)]
 pub struct Actions {
     pub player_movement: Option<Vec2>,
+    pub grab_pressed: bool,
+    pub grab_released: bool,
+    pub undo: bool,
+    pub restart: bool,
+    pub hint: bool,
+    pub undo_past_deadlock: bool,
+}
+
+impl Actions {
+    /// The grid direction of the current movement. When several keys are held,
+    /// W wins over A, A over S and S over D.
+    pub fn direction(&self) -> Direction {
+        match self.player_movement {
+            Some(movement) if movement.y > 0. => Direction::North,
+            Some(movement) if movement.x < 0. => Direction::West,
+            Some(movement) if movement.y < 0. => Direction::South,
+            Some(movement) if movement.x > 0. => Direction::East,
+            _ => Direction::None,
+        }
+    }
 }
 
 pub fn set_movement_actions(
@@ -41,13 +75,28 @@ pub fn set_movement_actions(
             - get_movement(GameControl::Down, &keyboard_input),
     );
 
-    if let Some(touch_position) = touch_input.first_pressed_position() {
-        let (camera, camera_transform) = camera.single();
+    let touch = touch_input
+        .first_pressed_position()
+        .zip(camera.get_single().ok());
+    if let Some((touch_position, (camera, camera_transform))) = touch {
         if let Some(touch_position) = camera.viewport_to_world_2d(camera_transform, touch_position)
         {
-            let diff = touch_position - player.single().translation.xy();
-            if diff.length() > FOLLOW_EPSILON {
-                player_movement = diff.normalize();
+            // steer from the middle of all players, which is just the player in most levels
+            let positions: Vec<Vec2> = player
+                .iter()
+                .map(|transform| transform.translation.xy())
+                .collect();
+            if !positions.is_empty() {
+                let center = positions.iter().sum::<Vec2>() / positions.len() as f32;
+                let diff = touch_position - center;
+                // steer along the dominant axis, the key priority is meant for keys
+                if diff.length() > FOLLOW_EPSILON {
+                    player_movement = if diff.x.abs() > diff.y.abs() {
+                        Vec2::new(diff.x.signum(), 0.)
+                    } else {
+                        Vec2::new(0., diff.y.signum())
+                    };
+                }
             }
         }
     }
@@ -57,4 +106,11 @@ pub fn set_movement_actions(
     } else {
         actions.player_movement = None;
     }
+
+    actions.grab_pressed = GameControl::Grab.just_pressed(&keyboard_input);
+    actions.grab_released = GameControl::Grab.just_released(&keyboard_input);
+    actions.undo = GameControl::Undo.just_pressed(&keyboard_input);
+    actions.restart = GameControl::Restart.just_pressed(&keyboard_input);
+    actions.hint = GameControl::Hint.just_pressed(&keyboard_input);
+    actions.undo_past_deadlock = GameControl::UndoPastDeadlock.just_pressed(&keyboard_input);
 }
@@ -0,0 +1,232 @@
+use crate::loading::AnimationAssets;
+use crate::player::{
+    handle_move_player, Direction, Grabbing, Player, PlayerMoveEvent, PullMoveEvent, PushMoveEvent,
+};
+use crate::progress::LevelCompleted;
+use crate::save::SaveData;
+use crate::tile_map::IsMoving;
+use crate::GameState;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub struct AnimationPlugin;
+
+// Player sprites are driven by a small state machine whose frames come from
+// assets/animations/player.anim.ron, so the art can change without touching code.
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<AnimationSet>()
+            .init_asset_loader::<AnimationSetLoader>()
+            .add_systems(
+                Update,
+                (
+                    insert_sprite_animations,
+                    start_move_animations.after(handle_move_player),
+                    celebrate_level_completed,
+                    update_sprite_animations
+                        .after(insert_sprite_animations)
+                        .after(start_move_animations)
+                        .after(celebrate_level_completed),
+                )
+                    // keeps the celebration going behind the results screen
+                    .run_if(
+                        in_state(GameState::Playing).or_else(in_state(GameState::LevelComplete)),
+                    ),
+            );
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AnimationState {
+    Idle,
+    Walk,
+    Push,
+    Pull,
+    Grab,
+    Celebrate,
+}
+
+impl AnimationState {
+    /// Move clips play exactly once over the grid tween, the others loop.
+    fn follows_move(&self) -> bool {
+        matches!(
+            self,
+            AnimationState::Walk | AnimationState::Push | AnimationState::Pull
+        )
+    }
+}
+
+/// Atlas indices of one clip for each facing. Side frames look west and are
+/// flipped for east.
+#[derive(Deserialize, Debug)]
+pub struct AnimationClip {
+    pub north: Vec<usize>,
+    pub side: Vec<usize>,
+    pub south: Vec<usize>,
+    /// Seconds per frame for looping clips.
+    #[serde(default = "default_frame_seconds")]
+    pub frame_seconds: f32,
+}
+
+fn default_frame_seconds() -> f32 {
+    0.25
+}
+
+impl AnimationClip {
+    fn frames(&self, direction: Direction) -> &[usize] {
+        match direction {
+            Direction::North => &self.north,
+            Direction::South => &self.south,
+            _ => &self.side,
+        }
+    }
+}
+
+#[derive(Deserialize, TypeUuid, TypePath, Debug)]
+#[uuid = "5e0b2a63-0c1f-4b53-9d5e-2f0a8f0f6c7e"]
+pub struct AnimationSet {
+    pub clips: HashMap<AnimationState, AnimationClip>,
+}
+
+#[derive(Default)]
+pub struct AnimationSetLoader;
+
+impl AssetLoader for AnimationSetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let animation_set: AnimationSet = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(animation_set));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.ron"]
+    }
+}
+
+#[derive(Component)]
+pub struct SpriteAnimation {
+    pub set: Handle<AnimationSet>,
+    pub state: AnimationState,
+    elapsed: Duration,
+}
+
+impl SpriteAnimation {
+    fn play(&mut self, state: AnimationState) {
+        self.state = state;
+        self.elapsed = Duration::ZERO;
+    }
+}
+
+fn insert_sprite_animations(
+    mut commands: Commands,
+    player_query: Query<Entity, (With<Player>, Without<SpriteAnimation>)>,
+    animation_assets: Res<AnimationAssets>,
+) {
+    for entity in player_query.iter() {
+        commands.entity(entity).insert(SpriteAnimation {
+            set: animation_assets.player.clone(),
+            state: AnimationState::Idle,
+            elapsed: Duration::ZERO,
+        });
+    }
+}
+
+fn start_move_animations(
+    mut ev_player_move: EventReader<PlayerMoveEvent>,
+    mut ev_push_move: EventReader<PushMoveEvent>,
+    mut ev_pull_move: EventReader<PullMoveEvent>,
+    mut animation_query: Query<&mut SpriteAnimation>,
+) {
+    let pushing = ev_push_move.iter().count() > 0;
+    let pulling = ev_pull_move.iter().count() > 0;
+    let state = if pushing {
+        AnimationState::Push
+    } else if pulling {
+        AnimationState::Pull
+    } else {
+        AnimationState::Walk
+    };
+    for PlayerMoveEvent(entity, _) in ev_player_move.iter() {
+        if let Ok(mut animation) = animation_query.get_mut(*entity) {
+            animation.play(state);
+        }
+    }
+}
+
+fn celebrate_level_completed(
+    mut ev_level_completed: EventReader<LevelCompleted>,
+    mut animation_query: Query<&mut SpriteAnimation>,
+) {
+    if ev_level_completed.iter().count() == 0 {
+        return;
+    }
+    for mut animation in animation_query.iter_mut() {
+        animation.play(AnimationState::Celebrate);
+    }
+}
+
+fn update_sprite_animations(
+    time: Res<Time>,
+    save_data: Res<SaveData>,
+    animation_sets: Res<Assets<AnimationSet>>,
+    mut animation_query: Query<(
+        &Player,
+        &mut SpriteAnimation,
+        &mut TextureAtlasSprite,
+        Option<&IsMoving>,
+        Option<&Grabbing>,
+    )>,
+) {
+    let move_duration = save_data.settings.move_duration();
+    for (player, mut animation, mut sprite, moving, grabbing) in animation_query.iter_mut() {
+        // once the tween is done, settle into whatever the player is doing now
+        let finished_move = animation.state.follows_move() && moving.is_none();
+        let settled = if grabbing.is_some() {
+            AnimationState::Grab
+        } else {
+            AnimationState::Idle
+        };
+        if (finished_move || matches!(animation.state, AnimationState::Idle | AnimationState::Grab))
+            && animation.state != settled
+        {
+            animation.play(settled);
+        }
+        animation.elapsed += time.delta();
+
+        let Some(clip) = animation_sets
+            .get(&animation.set)
+            .and_then(|animation_set| animation_set.clips.get(&animation.state))
+        else {
+            continue;
+        };
+        let direction = player.face_direction();
+        let frames = clip.frames(direction);
+        if frames.is_empty() {
+            continue;
+        }
+        let frame = if animation.state.follows_move() {
+            let progress = animation.elapsed.as_secs_f32() / move_duration.as_secs_f32();
+            ((progress * frames.len() as f32) as usize).min(frames.len() - 1)
+        } else {
+            (animation.elapsed.as_secs_f32() / clip.frame_seconds) as usize % frames.len()
+        };
+        let flip_x = matches!(direction, Direction::East | Direction::None);
+        if sprite.index != frames[frame] {
+            sprite.index = frames[frame];
+        }
+        if sprite.flip_x != flip_x {
+            sprite.flip_x = flip_x;
+        }
+    }
+}
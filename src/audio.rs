@@ -0,0 +1,255 @@
+use crate::actions::Actions;
+use crate::loading::AudioAssets;
+use crate::player::{
+    get_movement_coords_from_direction, handle_move_player, handle_move_player_event,
+    ungrab_from_release_input, Grabbing, MoveBlocked, PullMoveEvent, PushMoveEvent, TurnEvent,
+};
+use crate::progress::LevelCompleted;
+use crate::save::SaveData;
+use crate::tile_map::Goal;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_kira_audio::prelude::*;
+use std::time::Duration;
+
+pub struct InternalAudioPlugin;
+
+// Music and sound effects play on their own channels so their volumes can be
+// set separately from the settings screen. Gameplay systems don't play sounds
+// themselves, the systems below turn their events into `SfxEvent`s instead.
+// Music follows the `music` field of the spawned level.
+impl Plugin for InternalAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_channel::<MusicChannel>()
+            .add_audio_channel::<SfxChannel>()
+            .init_resource::<MusicManager>()
+            .add_event::<SfxEvent>()
+            .add_systems(
+                Update,
+                (
+                    sfx_from_moves
+                        .after(handle_move_player)
+                        .before(handle_move_player_event),
+                    sfx_from_blocked_moves.after(handle_move_player),
+                    sfx_from_grabs.before(ungrab_from_release_input),
+                    sfx_from_level_completed,
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Update,
+                (
+                    apply_volume_settings,
+                    play_level_music,
+                    play_sfx.run_if(resource_exists::<AudioAssets>()),
+                ),
+            );
+    }
+}
+
+/// Track for levels without a `music` field, relative to the assets folder.
+const DEFAULT_MUSIC: &str = "Bemuse.ogg";
+const CROSSFADE: Duration = Duration::from_secs(2);
+const VOLUME_FADE: Duration = Duration::from_millis(300);
+const PAUSE_DUCK: f64 = 0.3;
+
+#[derive(Resource)]
+pub struct MusicChannel;
+
+#[derive(Default, Resource)]
+pub struct MusicManager {
+    track: Option<String>,
+    instance: Option<Handle<AudioInstance>>,
+}
+
+#[derive(Resource)]
+pub struct SfxChannel;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Sfx {
+    Step,
+    Push,
+    Pull,
+    Grab,
+    Release,
+    Bump,
+    BlockOnGoal,
+    LevelComplete,
+}
+
+impl Sfx {
+    fn source(&self, audio_assets: &AudioAssets) -> Handle<AudioSource> {
+        match self {
+            Sfx::Step => audio_assets.step.clone(),
+            Sfx::Push => audio_assets.push.clone(),
+            Sfx::Pull => audio_assets.pull.clone(),
+            Sfx::Grab => audio_assets.grab.clone(),
+            Sfx::Release => audio_assets.release.clone(),
+            Sfx::Bump => audio_assets.bump.clone(),
+            Sfx::BlockOnGoal => audio_assets.goal.clone(),
+            Sfx::LevelComplete => audio_assets.level_complete.clone(),
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct SfxEvent(pub Sfx);
+
+fn apply_volume_settings(
+    save_data: Res<SaveData>,
+    music: Res<AudioChannel<MusicChannel>>,
+    sfx: Res<AudioChannel<SfxChannel>>,
+    state: Res<State<GameState>>,
+    mut applied: Local<Option<(f64, f64)>>,
+) {
+    // the music ducks under the pause menu
+    let duck = if *state.get() == GameState::Paused {
+        PAUSE_DUCK
+    } else {
+        1.
+    };
+    let volumes = (
+        save_data.settings.music_volume * duck,
+        save_data.settings.sfx_volume,
+    );
+    if *applied == Some(volumes) {
+        return;
+    }
+    *applied = Some(volumes);
+    music
+        .set_volume(volumes.0)
+        .fade_in(AudioTween::linear(VOLUME_FADE));
+    sfx.set_volume(volumes.1);
+}
+
+/// Plays the `music` field of every spawned level, crossfading when the track
+/// changes and carrying on untouched when the next level uses the same one.
+fn play_level_music(
+    mut level_events: EventReader<LevelEvent>,
+    ldtk_project_entities: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    asset_server: Res<AssetServer>,
+    music: Res<AudioChannel<MusicChannel>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut music_manager: ResMut<MusicManager>,
+) {
+    for level_event in level_events.iter() {
+        let LevelEvent::Spawned(level_iid) = level_event else {
+            continue;
+        };
+        let track = ldtk_project_entities
+            .iter()
+            .find_map(|handle| ldtk_project_assets.get(handle))
+            .and_then(|ldtk_project| ldtk_project.get_raw_level_by_iid(level_iid.get()))
+            .and_then(|level| level.get_string_field("music").ok().cloned())
+            .unwrap_or_else(|| DEFAULT_MUSIC.to_string());
+        if music_manager.track.as_ref() == Some(&track) {
+            continue;
+        }
+        if let Some(instance) = music_manager
+            .instance
+            .take()
+            .and_then(|handle| audio_instances.get_mut(&handle))
+        {
+            instance.stop(AudioTween::linear(CROSSFADE));
+        }
+        music_manager.instance = Some(
+            music
+                .play(asset_server.load(&track))
+                .looped()
+                .fade_in(AudioTween::linear(CROSSFADE))
+                .handle(),
+        );
+        music_manager.track = Some(track);
+    }
+}
+
+fn play_sfx(
+    mut ev_sfx: EventReader<SfxEvent>,
+    audio_assets: Res<AudioAssets>,
+    sfx: Res<AudioChannel<SfxChannel>>,
+) {
+    for SfxEvent(effect) in ev_sfx.iter() {
+        sfx.play(effect.source(&audio_assets));
+    }
+}
+
+// a turn plays a single sound, moving a block drowns out the footsteps
+fn sfx_from_moves(
+    mut turns: EventReader<TurnEvent>,
+    mut ev_push_move: EventReader<PushMoveEvent>,
+    mut ev_pull_move: EventReader<PullMoveEvent>,
+    grid_coords_query: Query<&GridCoords>,
+    goal_query: Query<&GridCoords, With<Goal>>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+) {
+    let turn_taken = turns.iter().count() > 0;
+    let pushed: Vec<_> = ev_push_move
+        .iter()
+        .map(|event| (event.0, event.1))
+        .collect();
+    let pulled: Vec<_> = ev_pull_move
+        .iter()
+        .map(|event| (event.0, event.1))
+        .collect();
+    let on_goal = pushed
+        .iter()
+        .chain(pulled.iter())
+        .any(|(entity, direction)| {
+            let (Ok(grid_coords), Some(offset)) = (
+                grid_coords_query.get(*entity),
+                get_movement_coords_from_direction(*direction),
+            ) else {
+                return false;
+            };
+            goal_query
+                .iter()
+                .any(|goal_grid_coords| *goal_grid_coords == *grid_coords + offset)
+        });
+
+    if !pushed.is_empty() {
+        ev_sfx.send(SfxEvent(Sfx::Push));
+    } else if !pulled.is_empty() {
+        ev_sfx.send(SfxEvent(Sfx::Pull));
+    } else if turn_taken {
+        ev_sfx.send(SfxEvent(Sfx::Step));
+    }
+    if on_goal {
+        ev_sfx.send(SfxEvent(Sfx::BlockOnGoal));
+    }
+}
+
+// a blocked move bumps for as long as a step takes, so holding a direction
+// against a wall repeats the sound at walking pace
+fn sfx_from_blocked_moves(
+    mut ev_move_blocked: EventReader<MoveBlocked>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+) {
+    if ev_move_blocked.iter().count() > 0 {
+        ev_sfx.send(SfxEvent(Sfx::Bump));
+    }
+}
+
+fn sfx_from_grabs(
+    actions: Res<Actions>,
+    added_grabbing_query: Query<(), Added<Grabbing>>,
+    grabbing_query: Query<(), With<Grabbing>>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+) {
+    if !added_grabbing_query.is_empty() {
+        ev_sfx.send(SfxEvent(Sfx::Grab));
+    }
+    if actions.grab_released && !grabbing_query.is_empty() {
+        ev_sfx.send(SfxEvent(Sfx::Release));
+    }
+}
+
+fn sfx_from_level_completed(
+    mut ev_level_completed: EventReader<LevelCompleted>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+) {
+    for _ in ev_level_completed.iter() {
+        ev_sfx.send(SfxEvent(Sfx::LevelComplete));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::pause::{resuming, LeaveLevel};
+use crate::player::Player;
+use crate::save::SaveData;
+use crate::GameState;
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_ecs_ldtk::prelude::*;
+
+pub struct CameraPlugin;
+
+// A single camera lives for the whole game. Screens tell it what to show through
+// `CameraTarget` and it eases there, zoomed by whole pixels only.
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraTarget>()
+            .add_systems(Startup, spawn_camera)
+            .add_systems(
+                Update,
+                (frame_active_level, follow_players.after(frame_active_level))
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Update,
+                move_camera.after(frame_active_level).after(follow_players),
+            )
+            .add_systems(
+                OnEnter(GameState::Playing),
+                letterbox_level.run_if(not(resuming)),
+            )
+            .add_systems(LeaveLevel, remove_letterbox);
+    }
+}
+
+/// How quickly the camera closes in on its target, per second.
+const CAMERA_SPEED: f32 = 8.;
+const LETTERBOX_COLOR: Color = Color::BLACK;
+
+#[derive(Component)]
+pub struct MainCamera;
+
+/// The part of the world the camera shows. `bounds` are shown whole at the
+/// largest integer zoom that fits the window; when they don't fit even
+/// unzoomed, the camera keeps `focus` in view without leaving the bounds.
+#[derive(Resource)]
+pub struct CameraTarget {
+    pub bounds: Rect,
+    pub focus: Option<Vec2>,
+    snap: bool,
+}
+
+impl Default for CameraTarget {
+    fn default() -> Self {
+        CameraTarget {
+            bounds: Rect::from_center_size(Vec2::new(128., -128.), Vec2::splat(256.)),
+            focus: None,
+            snap: true,
+        }
+    }
+}
+
+impl CameraTarget {
+    pub fn frame(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+        self.focus = None;
+    }
+}
+
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn((Camera2dBundle::default(), MainCamera));
+}
+
+fn letterbox_level(mut camera_query: Query<&mut Camera2d, With<MainCamera>>) {
+    for mut camera_2d in camera_query.iter_mut() {
+        camera_2d.clear_color = ClearColorConfig::Custom(LETTERBOX_COLOR);
+    }
+}
+
+fn remove_letterbox(mut camera_query: Query<&mut Camera2d, With<MainCamera>>) {
+    for mut camera_2d in camera_query.iter_mut() {
+        camera_2d.clear_color = ClearColorConfig::Default;
+    }
+}
+
+pub fn frame_active_level(
+    mut level_events: EventReader<LevelEvent>,
+    level_query: Query<(&LevelIid, &Transform)>,
+    ldtk_project_entities: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut camera_target: ResMut<CameraTarget>,
+) {
+    for level_event in level_events.iter() {
+        if let LevelEvent::Transformed(level_iid) = level_event {
+            let Some(level) = ldtk_project_entities
+                .iter()
+                .find_map(|handle| ldtk_project_assets.get(handle))
+                .and_then(|ldtk_project| ldtk_project.get_raw_level_by_iid(level_iid.get()))
+            else {
+                continue;
+            };
+            let Some((_, level_transform)) = level_query.iter().find(|(iid, _)| *iid == level_iid)
+            else {
+                continue;
+            };
+            // levels are anchored at their bottom left corner
+            let min = level_transform.translation.truncate();
+            camera_target.frame(Rect::from_corners(
+                min,
+                min + Vec2::new(level.px_wid as f32, level.px_hei as f32),
+            ));
+        }
+    }
+}
+
+// only matters for levels larger than the screen
+fn follow_players(
+    player_query: Query<&GlobalTransform, With<Player>>,
+    mut camera_target: ResMut<CameraTarget>,
+) {
+    let count = player_query.iter().count();
+    if count == 0 {
+        return;
+    }
+    let sum: Vec2 = player_query
+        .iter()
+        .map(|transform| transform.translation().truncate())
+        .sum();
+    camera_target.focus = Some(sum / count as f32);
+}
+
+fn clamp_axis(min: f32, max: f32, half_visible: f32, focus: f32) -> f32 {
+    if max - min <= half_visible * 2. {
+        (min + max) / 2.
+    } else {
+        focus.clamp(min + half_visible, max - half_visible)
+    }
+}
+
+fn move_camera(
+    time: Res<Time>,
+    save_data: Res<SaveData>,
+    mut camera_target: ResMut<CameraTarget>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let Ok((mut transform, mut projection)) = camera_query.get_single_mut() else {
+        return;
+    };
+    let bounds = camera_target.bounds;
+    if bounds.is_empty() {
+        return;
+    }
+
+    // zoom in whole physical pixels per world pixel, so sprites stay crisp
+    let physical_size = Vec2::new(
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+    let zoom = (physical_size / bounds.size())
+        .min_element()
+        .floor()
+        .max(1.);
+    let scale = window.scale_factor() as f32 / zoom;
+    let half_visible = physical_size / zoom / 2.;
+    let focus = camera_target.focus.unwrap_or(bounds.center());
+    let center = Vec2::new(
+        clamp_axis(bounds.min.x, bounds.max.x, half_visible.x, focus.x),
+        clamp_axis(bounds.min.y, bounds.max.y, half_visible.y, focus.y),
+    );
+
+    let step = if camera_target.snap || save_data.settings.reduced_motion {
+        1.
+    } else {
+        1. - (-CAMERA_SPEED * time.delta_seconds()).exp()
+    };
+    camera_target.snap = false;
+    let translation = transform.translation.truncate().lerp(center, step);
+    // snap to the pixel grid once settled
+    let translation = if translation.distance(center) < 1. / zoom {
+        (center * zoom).round() / zoom
+    } else {
+        translation
+    };
+    let scale = if (scale - projection.scale).abs() < 0.001 {
+        scale
+    } else {
+        projection.scale + (scale - projection.scale) * step
+    };
+    if transform.translation.truncate() != translation {
+        transform.translation.x = translation.x;
+        transform.translation.y = translation.y;
+    }
+    if projection.scale != scale {
+        projection.scale = scale;
+    }
+}
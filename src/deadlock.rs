@@ -0,0 +1,188 @@
+use crate::actions::Actions;
+use crate::hazards::PlayerDeath;
+use crate::hints::PuzzleSource;
+use crate::history::{undo_turn, MoveHistory, RestartEvent, UndoEvent};
+use crate::pause::LeaveLevel;
+use crate::player::{Movable, TurnEvent};
+use crate::solver::{undos_to_solvable, Deadlock};
+use crate::tile_map::IsMoving;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_ecs_ldtk::prelude::*;
+
+pub struct DeadlockPlugin;
+
+// After every turn the level is checked for positions that can't be won any
+// more. A small warning offers to undo past the turn that caused the deadlock,
+// the solver then finds how far back the level was last winnable.
+impl Plugin for DeadlockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DeadlockWarning>()
+            .init_resource::<UndoPastDeadlockTask>()
+            .add_systems(
+                Update,
+                (
+                    check_deadlock,
+                    cancel_undo_past_deadlock.before(undo_past_deadlock),
+                    undo_past_deadlock,
+                    finish_undo_past_deadlock
+                        .after(undo_past_deadlock)
+                        .before(undo_turn),
+                    show_deadlock_warning.after(check_deadlock),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(LeaveLevel, cleanup_deadlock_warning);
+    }
+}
+
+/// The deadlock found after the latest turn, if any.
+#[derive(Default, Resource)]
+pub struct DeadlockWarning(pub Option<Deadlock>);
+
+impl Deadlock {
+    fn message(&self) -> &'static str {
+        match self {
+            Deadlock::StuckBlock(_) => "A block can't reach any goal any more.",
+            Deadlock::FrozenBlocks => "Some blocks are wedged in for good.",
+            Deadlock::UnreachableGoal(_) => "A goal can't be reached any more.",
+        }
+    }
+}
+
+/// The search for the last solvable turn, started by pressing U.
+#[derive(Default, Resource)]
+struct UndoPastDeadlockTask(Option<Task<Option<usize>>>);
+
+#[derive(Component)]
+struct DeadlockText;
+
+// like level completion, wait for the turn's tweens before judging it
+fn check_deadlock(
+    mut pending: Local<bool>,
+    changed_query: Query<(), (Changed<GridCoords>, With<Movable>)>,
+    moving_query: Query<(), With<IsMoving>>,
+    player_death: Res<PlayerDeath>,
+    puzzle_source: PuzzleSource,
+    mut warning: ResMut<DeadlockWarning>,
+) {
+    if !changed_query.is_empty() {
+        *pending = true;
+    }
+    if !*pending || !moving_query.is_empty() || player_death.is_dead() {
+        return;
+    }
+    *pending = false;
+
+    let deadlock = if puzzle_source.is_supported() {
+        let (puzzle, state) = puzzle_source.current();
+        puzzle.deadlock(&state)
+    } else {
+        None
+    };
+    if warning.0 != deadlock {
+        warning.0 = deadlock;
+    }
+}
+
+// the warning comes from quick checks, but how far to go back is up to the
+// solver, which runs on the async compute pool like hints do
+fn undo_past_deadlock(
+    actions: Res<Actions>,
+    warning: Res<DeadlockWarning>,
+    puzzle_source: PuzzleSource,
+    history: Res<MoveHistory>,
+    mut undo_task: ResMut<UndoPastDeadlockTask>,
+) {
+    if !actions.undo_past_deadlock || warning.0.is_none() || undo_task.0.is_some() {
+        return;
+    }
+    let earlier = history
+        .turns()
+        .iter()
+        .rev()
+        .map(|snapshot| puzzle_source.before(snapshot))
+        .collect();
+    undo_task.0 =
+        Some(AsyncComputeTaskPool::get().spawn(async move { undos_to_solvable(earlier) }));
+}
+
+fn finish_undo_past_deadlock(
+    mut undo_task: ResMut<UndoPastDeadlockTask>,
+    mut ev_undo: EventWriter<UndoEvent>,
+    mut ev_restart: EventWriter<RestartEvent>,
+) {
+    if !undo_task.0.as_ref().is_some_and(|task| task.is_finished()) {
+        return;
+    }
+    let Some(task) = undo_task.0.take() else {
+        return;
+    };
+    match block_on(task) {
+        Some(undos) => {
+            for _ in 0..undos {
+                ev_undo.send(UndoEvent);
+            }
+        }
+        None => ev_restart.send(RestartEvent),
+    }
+}
+
+// a search started before the level changed would undo the wrong turns
+fn cancel_undo_past_deadlock(
+    mut turns: EventReader<TurnEvent>,
+    mut ev_undo: EventReader<UndoEvent>,
+    mut ev_restart: EventReader<RestartEvent>,
+    mut undo_task: ResMut<UndoPastDeadlockTask>,
+) {
+    if turns.iter().count() + ev_undo.iter().count() + ev_restart.iter().count() > 0 {
+        undo_task.0 = None;
+    }
+}
+
+fn show_deadlock_warning(
+    mut commands: Commands,
+    warning: Res<DeadlockWarning>,
+    text_query: Query<Entity, With<DeadlockText>>,
+) {
+    if !warning.is_changed() {
+        return;
+    }
+    for entity in text_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(deadlock) = warning.0 else {
+        return;
+    };
+    commands.spawn((
+        TextBundle::from_section(
+            format!("{} Press U to undo past the deadlock.", deadlock.message()),
+            TextStyle {
+                font_size: 18.0,
+                color: Color::rgb(1.0, 0.6, 0.5),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            right: Val::Px(10.),
+            ..default()
+        }),
+        DeadlockText,
+    ));
+}
+
+fn cleanup_deadlock_warning(
+    mut commands: Commands,
+    text_query: Query<Entity, With<DeadlockText>>,
+    mut warning: ResMut<DeadlockWarning>,
+    mut undo_task: ResMut<UndoPastDeadlockTask>,
+) {
+    warning.0 = None;
+    undo_task.0 = None;
+    for entity in text_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
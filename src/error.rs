@@ -0,0 +1,216 @@
+use crate::menu::ButtonColors;
+use crate::tile_map::LevelMembership;
+use crate::GameState;
+use bevy::prelude::*;
+use std::fmt;
+
+pub struct ErrorPlugin;
+
+// Systems that run into something they can't recover from send a `GameError`
+// instead of panicking. The level is left behind and an error screen explains
+// what happened before going back to the menu.
+impl Plugin for ErrorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GameError>()
+            .add_systems(
+                Update,
+                report_game_errors.run_if(not(in_state(GameState::Error))),
+            )
+            .add_systems(OnEnter(GameState::Error), setup_error_screen)
+            .add_systems(
+                Update,
+                leave_error_screen.run_if(in_state(GameState::Error)),
+            )
+            .add_systems(OnExit(GameState::Error), cleanup_error_screen);
+    }
+}
+
+#[derive(Event, Clone, Debug)]
+pub enum GameError {
+    /// The level with this iid spawned before the LDtk project it belongs to was loaded.
+    ProjectNotLoaded(String),
+    /// A spawned level isn't part of the LDtk project.
+    LevelNotFound(String),
+    /// An entity that should have had the named component didn't.
+    MissingComponent(Entity, &'static str),
+    /// A replayed action was due at a different turn than the level reached.
+    ReplayOutOfSync {
+        level_iid: String,
+        expected: u32,
+        taken: u32,
+    },
+}
+
+impl GameError {
+    /// The level the error happened in, entities are looked up in the levels they belong to.
+    fn level_iid(&self, membership: &LevelMembership) -> Option<String> {
+        match self {
+            GameError::ProjectNotLoaded(level_iid)
+            | GameError::LevelNotFound(level_iid)
+            | GameError::ReplayOutOfSync { level_iid, .. } => Some(level_iid.clone()),
+            GameError::MissingComponent(entity, _) => membership
+                .level_iid(*entity)
+                .map(|level_iid| level_iid.get().clone()),
+        }
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::ProjectNotLoaded(_) => write!(f, "the level data isn't loaded"),
+            GameError::LevelNotFound(level_iid) => {
+                write!(f, "level {level_iid} doesn't exist in the level data")
+            }
+            GameError::MissingComponent(entity, component) => {
+                write!(f, "{entity:?} has no {component}")
+            }
+            GameError::ReplayOutOfSync {
+                expected, taken, ..
+            } => write!(
+                f,
+                "the replay went out of sync at turn {taken}, its next action was recorded at turn {expected}"
+            ),
+        }
+    }
+}
+
+/// The error shown on the error screen and the level it happened in.
+#[derive(Resource)]
+pub struct GameErrorReport {
+    pub error: GameError,
+    pub level_iid: Option<String>,
+}
+
+#[derive(Component)]
+struct ErrorScreen;
+
+fn report_game_errors(
+    mut commands: Commands,
+    mut ev_game_error: EventReader<GameError>,
+    membership: LevelMembership,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let mut errors = ev_game_error.iter();
+    let Some(error) = errors.next() else {
+        return;
+    };
+    for error in std::iter::once(error).chain(errors) {
+        match error.level_iid(&membership) {
+            Some(level_iid) => error!("{error} (in level {level_iid})"),
+            None => error!("{error}"),
+        }
+    }
+    // only the first error is shown, the rest usually follow from it
+    commands.insert_resource(GameErrorReport {
+        error: error.clone(),
+        level_iid: error.level_iid(&membership),
+    });
+    next_state.set(GameState::Error);
+}
+
+fn setup_error_screen(mut commands: Commands, report: Option<Res<GameErrorReport>>) {
+    let details = match report.as_deref() {
+        Some(GameErrorReport {
+            error,
+            level_iid: Some(level_iid),
+        }) => format!("In level {level_iid}: {error}."),
+        Some(GameErrorReport {
+            error,
+            level_iid: None,
+        }) => format!("{error}."),
+        None => String::new(),
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            ErrorScreen,
+        ))
+        .with_children(|children| {
+            children.spawn(TextBundle::from_section(
+                "Something went wrong",
+                TextStyle {
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+            children.spawn(
+                TextBundle::from_section(
+                    details,
+                    TextStyle {
+                        font_size: 20.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(10.)),
+                    ..default()
+                }),
+            );
+            let button_colors = ButtonColors::default();
+            children
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(220.0),
+                            height: Val::Px(50.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: button_colors.normal.into(),
+                        ..default()
+                    },
+                    button_colors,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Back to Menu",
+                        TextStyle {
+                            font_size: 30.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                            ..default()
+                        },
+                    ));
+                });
+        });
+}
+
+fn leave_error_screen(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut interaction_query: Query<
+        (&Interaction, &ButtonColors, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.any_just_pressed([KeyCode::Escape, KeyCode::Return, KeyCode::Space]) {
+        next_state.set(GameState::Menu);
+    }
+    for (interaction, button_colors, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => next_state.set(GameState::Menu),
+            Interaction::Hovered => *color = button_colors.hovered.into(),
+            Interaction::None => *color = button_colors.normal.into(),
+        }
+    }
+}
+
+fn cleanup_error_screen(mut commands: Commands, query: Query<Entity, With<ErrorScreen>>) {
+    commands.remove_resource::<GameErrorReport>();
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
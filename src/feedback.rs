@@ -0,0 +1,168 @@
+use crate::gravity::LevelRules;
+use crate::pause::LeaveLevel;
+use crate::player::{
+    get_movement_coords_from_direction, handle_move_player, BlockedReason, MoveBlocked,
+};
+use crate::save::SaveData;
+use crate::tile_map::{IsMoving, GRID_SIZE};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_tweening::{lens::TransformPositionLens, Animator, EaseFunction, Tween};
+
+pub struct FeedbackPlugin;
+
+// Blocked moves nudge the mover towards the obstacle and back, and tutorial
+// levels additionally explain what stopped it.
+impl Plugin for FeedbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                bump_blocked_movers.after(handle_move_player),
+                explain_blocked_moves.after(handle_move_player),
+                fade_blocked_tooltip,
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(LeaveLevel, cleanup_blocked_tooltip);
+    }
+}
+
+/// How far towards the obstacle a bump goes, as a fraction of a cell.
+const BUMP_DISTANCE: f32 = 0.25;
+const TOOLTIP_SECONDS: f32 = 2.5;
+
+#[derive(Component)]
+struct BlockedTooltip {
+    timer: Timer,
+}
+
+impl BlockedReason {
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            BlockedReason::Wall => "Something solid is in the way.",
+            BlockedReason::UnpushableBlock => "Blocks only move while you grab them (hold Space).",
+            BlockedReason::ChainTooLong => "That's more than you can push at once here.",
+            BlockedReason::GrabDirectionMismatch => {
+                "While grabbing you can only push or pull straight ahead."
+            }
+            BlockedReason::NoLadder => "You need a ladder to climb up.",
+        }
+    }
+}
+
+// the bump takes as long as a move and marks the mover as moving, so holding
+// a direction against a wall bumps at the same pace as walking
+fn bump_blocked_movers(
+    mut commands: Commands,
+    mut ev_move_blocked: EventReader<MoveBlocked>,
+    transform_query: Query<(&Transform, &GridCoords), Without<IsMoving>>,
+    save_data: Res<SaveData>,
+) {
+    let settings = &save_data.settings;
+    for move_blocked in ev_move_blocked.iter() {
+        let Ok((transform, grid_coords)) = transform_query.get(move_blocked.entity) else {
+            continue;
+        };
+        let Some(offset) = get_movement_coords_from_direction(move_blocked.direction) else {
+            continue;
+        };
+        let rest =
+            bevy_ecs_ldtk::utils::grid_coords_to_translation(*grid_coords, IVec2::splat(GRID_SIZE))
+                .extend(transform.translation.z);
+        // reduced motion keeps the pacing but leaves the sprite in place
+        let distance = if settings.reduced_motion {
+            0.
+        } else {
+            BUMP_DISTANCE * GRID_SIZE as f32
+        };
+        let bumped = rest + Vec3::new(offset.x as f32, offset.y as f32, 0.) * distance;
+        let half_duration = settings.move_duration() / 2;
+        let tween = Tween::new(
+            EaseFunction::QuadraticOut,
+            half_duration,
+            TransformPositionLens {
+                start: rest,
+                end: bumped,
+            },
+        )
+        .then(
+            Tween::new(
+                EaseFunction::QuadraticIn,
+                half_duration,
+                TransformPositionLens {
+                    start: bumped,
+                    end: rest,
+                },
+            )
+            .with_completed_event(0),
+        );
+        commands
+            .entity(move_blocked.entity)
+            .insert((IsMoving, Animator::new(tween)));
+    }
+}
+
+fn explain_blocked_moves(
+    mut commands: Commands,
+    mut ev_move_blocked: EventReader<MoveBlocked>,
+    level_rules: Res<LevelRules>,
+    mut tooltip_query: Query<(&mut Text, &mut BlockedTooltip)>,
+) {
+    let Some(move_blocked) = ev_move_blocked.iter().last() else {
+        return;
+    };
+    if !level_rules.tutorial {
+        return;
+    }
+    let explanation = move_blocked.reason.explanation();
+    if let Ok((mut text, mut tooltip)) = tooltip_query.get_single_mut() {
+        text.sections[0].value = explanation.to_string();
+        tooltip.timer.reset();
+        return;
+    }
+    commands.spawn((
+        TextBundle::from_section(
+            explanation,
+            TextStyle {
+                font_size: 20.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(40.),
+            left: Val::Px(10.),
+            ..default()
+        }),
+        BlockedTooltip {
+            timer: Timer::from_seconds(TOOLTIP_SECONDS, TimerMode::Once),
+        },
+    ));
+}
+
+fn fade_blocked_tooltip(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut tooltip_query: Query<(Entity, &mut Text, &mut BlockedTooltip)>,
+) {
+    for (entity, mut text, mut tooltip) in tooltip_query.iter_mut() {
+        tooltip.timer.tick(time.delta());
+        if tooltip.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        text.sections[0]
+            .style
+            .color
+            .set_a(tooltip.timer.percent_left().min(0.25) * 4.);
+    }
+}
+
+fn cleanup_blocked_tooltip(mut commands: Commands, query: Query<Entity, With<BlockedTooltip>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
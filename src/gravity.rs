@@ -0,0 +1,128 @@
+use crate::hazards::PlayerDeath;
+use crate::occupancy::{update_occupancy, Occupancy};
+use crate::player::{GlobalPlayerState, Grabbed, Grabbing, Movable, Player};
+use crate::tile_map::{EntityWalls, IsMoving, Tile, WallBundle};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+pub struct GravityPlugin;
+
+impl Plugin for GravityPlugin {
+    fn build(&self, app: &mut App) {
+        // side-view levels keep their collisions on a "Collisions" layer,
+        // where 2 is a ladder and 3 is just another kind of solid ground
+        app.init_resource::<LevelRules>()
+            .register_ldtk_int_cell_for_layer::<LadderBundle>("Collisions", 2)
+            .register_ldtk_int_cell_for_layer::<WallBundle>("Collisions", 3)
+            .add_systems(
+                Update,
+                (load_level_rules, apply_gravity.after(update_occupancy))
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Per-level ruleset, read from the LDtk level fields when the level spawns.
+#[derive(Default, Resource)]
+pub struct LevelRules {
+    pub gravity: bool,
+    /// Move count needed for a gold medal, from the `par` level field.
+    pub par: Option<u32>,
+    /// Longest line of movables a single move may shove, from `push_limit`.
+    pub push_limit: Option<u32>,
+    /// Tutorial levels explain why a move was blocked.
+    pub tutorial: bool,
+}
+
+#[derive(Default, Component)]
+pub struct Ladder;
+
+#[derive(Default, Bundle, LdtkIntCell)]
+pub struct LadderBundle {
+    ladder: Ladder,
+}
+
+pub fn load_level_rules(
+    mut level_rules: ResMut<LevelRules>,
+    mut level_events: EventReader<LevelEvent>,
+    ldtk_project_entities: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+) {
+    for level_event in level_events.iter() {
+        if let LevelEvent::Spawned(level_iid) = level_event {
+            let Some(level) = ldtk_project_entities
+                .iter()
+                .find_map(|handle| ldtk_project_assets.get(handle))
+                .and_then(|ldtk_project| ldtk_project.get_raw_level_by_iid(level_iid.get()))
+            else {
+                continue;
+            };
+            level_rules.gravity = *level.get_bool_field("gravity").unwrap_or(&false);
+            level_rules.par = level
+                .get_int_field("par")
+                .ok()
+                .and_then(|par| u32::try_from(*par).ok());
+            level_rules.push_limit = level
+                .get_int_field("push_limit")
+                .ok()
+                .and_then(|push_limit| u32::try_from(*push_limit).ok());
+            level_rules.tutorial = *level.get_bool_field("tutorial").unwrap_or(&false);
+        }
+    }
+}
+
+// moves everything that isn't supported one cell down, waiting for the
+// previous step's tween to finish so falls animate through the same pipeline
+pub fn apply_gravity(
+    mut commands: Commands,
+    level_rules: Res<LevelRules>,
+    walls: EntityWalls,
+    mut occupancy: ResMut<Occupancy>,
+    player_death: Res<PlayerDeath>,
+    moving_query: Query<(), With<IsMoving>>,
+    mut movable_query: Query<
+        (
+            Entity,
+            &mut GridCoords,
+            Option<&Player>,
+            Option<&Grabbed>,
+            Option<&Grabbing>,
+        ),
+        With<Movable>,
+    >,
+    mut global_player_state: ResMut<GlobalPlayerState>,
+) {
+    if !level_rules.gravity || player_death.is_dead() || !moving_query.is_empty() {
+        return;
+    }
+
+    let mut movables: Vec<_> = movable_query.iter_mut().collect();
+    // settle from the bottom up, so stacks fall together
+    movables.sort_by_key(|(_, grid_coords, ..)| grid_coords.y);
+
+    let mut released_grab = false;
+    for (entity, grid_coords, player, grabbed, grabbing) in movables.iter_mut() {
+        let below = **grid_coords + GridCoords::new(0, -1);
+        let on_ladder = player.is_some()
+            && (walls.tile(*entity, grid_coords) == Some(Tile::Ladder)
+                || walls.tile(*entity, &below) == Some(Tile::Ladder));
+        let world_below = walls.of(*entity).to_world(below);
+        if on_ladder || walls.in_wall(*entity, &below) || occupancy.get(world_below).is_some() {
+            continue;
+        }
+        // keep the index up to date right away, so whatever rests on top falls too
+        occupancy.move_to(*entity, world_below);
+        **grid_coords = below;
+        commands.entity(*entity).insert(IsMoving);
+        released_grab |= grabbed.is_some() || grabbing.is_some();
+    }
+
+    // a grab can't survive a fall
+    if released_grab {
+        for (entity, ..) in movables.iter() {
+            commands.entity(*entity).remove::<(Grabbed, Grabbing)>();
+        }
+        global_player_state.grabbing = false;
+    }
+}
@@ -0,0 +1,370 @@
+use crate::history::{undo_turn, RestartEvent, UndoEvent};
+use crate::occupancy::Occupancy;
+use crate::pause::LeaveLevel;
+use crate::player::{
+    get_movement_coords_from_direction, handle_move_player_event, Player, PlayerMoveEvent,
+    TurnEvent,
+};
+use crate::save::SaveData;
+use crate::tile_map::{translate_grid_coords_entities, Block, EntityWalls, GRID_SIZE};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_tweening::{
+    lens::{TransformPositionLens, TransformScaleLens},
+    Animator, EaseFunction, Tween,
+};
+use std::collections::HashSet;
+use std::time::Duration;
+
+pub struct HazardsPlugin;
+
+impl Plugin for HazardsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerDeath>()
+            .add_event::<PlayerDied>()
+            .register_ldtk_entity::<EnemyBundle>("Enemy")
+            // the hub's water and the platformer's ladders are a 2 on their own layers
+            .register_ldtk_int_cell_for_layer::<SpikeBundle>("Walls", 2)
+            .add_systems(
+                Update,
+                (
+                    resolve_enemy_paths,
+                    move_enemies.after(handle_move_player_event),
+                    check_player_hazards.after(move_enemies),
+                    start_player_death
+                        .after(check_player_hazards)
+                        .after(translate_grid_coords_entities),
+                    tick_player_death.after(start_player_death),
+                    clear_player_death.before(undo_turn),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(LeaveLevel, reset_player_death);
+    }
+}
+
+const DEATH_ANIMATION_MILLIS: u64 = 400;
+const AUTO_RESTART_SECONDS: f32 = 3.0;
+
+/// Sent when a player ends up on the same cell as an enemy or a spike, or swaps
+/// cells with an enemy.
+#[derive(Event)]
+pub struct PlayerDied(pub Entity);
+
+#[derive(Default, Component)]
+pub struct Spike;
+
+#[derive(Default, Bundle, LdtkIntCell)]
+pub struct SpikeBundle {
+    spike: Spike,
+}
+
+/// Patrol route as read from the LDtk `path` field, relative to the spawn cell.
+#[derive(Default, Component)]
+pub struct EnemyPath(Vec<GridCoords>);
+
+impl From<&EntityInstance> for EnemyPath {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        // ldtk points count rows downwards, grid coords count them upwards
+        let offsets = entity_instance
+            .get_points_field("path")
+            .map(|points| {
+                points
+                    .iter()
+                    .map(|point| {
+                        GridCoords::new(
+                            point.x - entity_instance.grid.x,
+                            entity_instance.grid.y - point.y,
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        EnemyPath(offsets)
+    }
+}
+
+#[derive(Default, Clone, Component)]
+pub struct Enemy {
+    path: Vec<GridCoords>,
+    target: usize,
+    reversed: bool,
+}
+
+impl Enemy {
+    /// Returns the next cell on the way to the current waypoint,
+    /// turning around at either end of the path.
+    fn next_step(&mut self, current: GridCoords) -> Option<GridCoords> {
+        if self.path.len() < 2 {
+            return None;
+        }
+        if current == self.path[self.target] {
+            self.advance_target();
+        }
+        let target = self.path[self.target];
+        let step = if target.x != current.x {
+            GridCoords::new((target.x - current.x).signum(), 0)
+        } else {
+            GridCoords::new(0, (target.y - current.y).signum())
+        };
+        Some(current + step)
+    }
+
+    fn advance_target(&mut self) {
+        if self.reversed {
+            if self.target == 0 {
+                self.reversed = false;
+                self.target = 1;
+            } else {
+                self.target -= 1;
+            }
+        } else if self.target + 1 == self.path.len() {
+            self.reversed = true;
+            self.target -= 1;
+        } else {
+            self.target += 1;
+        }
+    }
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct EnemyBundle {
+    #[from_entity_instance]
+    path: EnemyPath,
+    #[sprite_sheet_bundle]
+    sprite_bundle: SpriteSheetBundle,
+    #[grid_coords]
+    grid_coords: GridCoords,
+}
+
+pub fn resolve_enemy_paths(
+    mut commands: Commands,
+    enemy_query: Query<(Entity, &GridCoords, &EnemyPath), Added<EnemyPath>>,
+) {
+    for (entity, grid_coords, enemy_path) in enemy_query.iter() {
+        let path = std::iter::once(*grid_coords)
+            .chain(enemy_path.0.iter().map(|offset| *grid_coords + *offset))
+            .collect();
+        commands.entity(entity).insert(Enemy {
+            path,
+            target: 0,
+            reversed: false,
+        });
+    }
+}
+
+pub fn move_enemies(
+    mut turns: EventReader<TurnEvent>,
+    mut ev_player_move: EventReader<PlayerMoveEvent>,
+    mut enemy_query: Query<
+        (Entity, &mut Enemy, &mut GridCoords),
+        (Without<Block>, Without<Player>),
+    >,
+    player_query: Query<&GridCoords, With<Player>>,
+    occupancy: Res<Occupancy>,
+    walls: EntityWalls,
+    mut ev_player_died: EventWriter<PlayerDied>,
+) {
+    // the players' grid coords already moved on, so work out where they came from
+    let player_steps: Vec<(Entity, GridCoords, GridCoords)> = ev_player_move
+        .iter()
+        .filter_map(|PlayerMoveEvent(entity, direction)| {
+            let destination = *player_query.get(*entity).ok()?;
+            let offset = get_movement_coords_from_direction(*direction)?;
+            let origin = GridCoords::new(destination.x - offset.x, destination.y - offset.y);
+            let level_walls = walls.of(*entity);
+            Some((
+                *entity,
+                level_walls.to_world(origin),
+                level_walls.to_world(destination),
+            ))
+        })
+        .collect();
+    for _ in turns.iter() {
+        for (entity, mut enemy, mut grid_coords) in enemy_query.iter_mut() {
+            let Some(destination) = enemy.next_step(*grid_coords) else {
+                continue;
+            };
+            // blocks and walls make the enemy wait for its path to clear
+            let world_destination = walls.of(entity).to_world(destination);
+            if walls.in_wall(entity, &destination)
+                || occupancy
+                    .get(world_destination)
+                    .map_or(false, |occupant| occupant.is_block())
+            {
+                continue;
+            }
+            let world_origin = walls.of(entity).to_world(*grid_coords);
+            *grid_coords = destination;
+            // a player and an enemy swapping cells run into each other halfway
+            for (player, from, to) in player_steps.iter() {
+                if *from == world_destination && *to == world_origin {
+                    ev_player_died.send(PlayerDied(*player));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default, Resource)]
+pub struct PlayerDeath {
+    timer: Option<Timer>,
+}
+
+impl PlayerDeath {
+    pub fn is_dead(&self) -> bool {
+        self.timer.is_some()
+    }
+}
+
+pub fn player_alive(player_death: Res<PlayerDeath>) -> bool {
+    !player_death.is_dead()
+}
+
+#[derive(Default, Component)]
+pub struct Dying;
+
+#[derive(Component)]
+pub struct DeathPrompt;
+
+pub fn check_player_hazards(
+    player_query: Query<(Entity, &GridCoords), With<Player>>,
+    hazard_query: Query<(Entity, &GridCoords), Or<(With<Enemy>, With<Spike>)>>,
+    changed_query: Query<(), (Changed<GridCoords>, Or<(With<Player>, With<Enemy>)>)>,
+    walls: EntityWalls,
+    player_death: Res<PlayerDeath>,
+    mut ev_player_died: EventWriter<PlayerDied>,
+) {
+    if changed_query.is_empty() || player_death.is_dead() {
+        return;
+    }
+    // compared in world space, the hazards may sit in a neighbouring level
+    let hazards: HashSet<GridCoords> = hazard_query
+        .iter()
+        .map(|(entity, grid_coords)| walls.of(entity).to_world(*grid_coords))
+        .collect();
+    for (entity, player_grid_coords) in player_query.iter() {
+        if hazards.contains(&walls.of(entity).to_world(*player_grid_coords)) {
+            ev_player_died.send(PlayerDied(entity));
+        }
+    }
+}
+
+fn grid_translation(grid_coords: GridCoords) -> Vec3 {
+    bevy_ecs_ldtk::utils::grid_coords_to_translation(grid_coords, IVec2::splat(GRID_SIZE))
+        .extend(0.)
+}
+
+// the death tween takes the place of the movement tween, so it finishes the
+// step that led here before shrinking the player
+pub fn start_player_death(
+    mut commands: Commands,
+    mut ev_player_died: EventReader<PlayerDied>,
+    mut player_death: ResMut<PlayerDeath>,
+    player_query: Query<(&Transform, &GridCoords)>,
+    save_data: Res<SaveData>,
+) {
+    let settings = &save_data.settings;
+    for PlayerDied(entity) in ev_player_died.iter() {
+        if player_death.timer.is_none() {
+            player_death.timer = Some(Timer::from_seconds(AUTO_RESTART_SECONDS, TimerMode::Once));
+        }
+        let Ok((transform, grid_coords)) = player_query.get(*entity) else {
+            continue;
+        };
+        let step = Tween::new(
+            settings.ease(EaseFunction::QuadraticInOut),
+            settings.move_duration(),
+            TransformPositionLens {
+                start: transform.translation,
+                end: grid_translation(*grid_coords),
+            },
+        )
+        .with_completed_event(0);
+        let shrink = Tween::new(
+            settings.ease(EaseFunction::BackIn),
+            Duration::from_millis(DEATH_ANIMATION_MILLIS),
+            TransformScaleLens {
+                start: transform.scale,
+                end: Vec3::ZERO,
+            },
+        )
+        .with_completed_event(0);
+        commands
+            .entity(*entity)
+            .insert((Dying, Animator::new(step.then(shrink))));
+    }
+}
+
+pub fn tick_player_death(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut player_death: ResMut<PlayerDeath>,
+    prompt_query: Query<Entity, With<DeathPrompt>>,
+    mut ev_restart: EventWriter<RestartEvent>,
+) {
+    let Some(timer) = player_death.timer.as_mut() else {
+        return;
+    };
+    timer.tick(time.delta());
+    if timer.elapsed() >= Duration::from_millis(DEATH_ANIMATION_MILLIS) && prompt_query.is_empty() {
+        commands.spawn((
+            TextBundle::from_section(
+                "You died! Press Z to undo or R to restart",
+                TextStyle {
+                    font_size: 24.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.),
+                left: Val::Px(10.),
+                ..default()
+            }),
+            DeathPrompt,
+        ));
+    }
+    if timer.just_finished() {
+        ev_restart.send(RestartEvent);
+    }
+}
+
+pub fn clear_player_death(
+    mut commands: Commands,
+    mut ev_undo: EventReader<UndoEvent>,
+    mut ev_restart: EventReader<RestartEvent>,
+    mut player_death: ResMut<PlayerDeath>,
+    mut dying_query: Query<(Entity, &mut Transform, &GridCoords), With<Dying>>,
+    prompt_query: Query<Entity, With<DeathPrompt>>,
+) {
+    if ev_undo.iter().count() + ev_restart.iter().count() == 0 {
+        return;
+    }
+    player_death.timer = None;
+    // undo and restart tween from wherever the player died
+    for (entity, mut transform, grid_coords) in dying_query.iter_mut() {
+        transform.translation = grid_translation(*grid_coords);
+        transform.scale = Vec3::ONE;
+        commands
+            .entity(entity)
+            .remove::<(Dying, Animator<Transform>)>();
+    }
+    for entity in prompt_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// the level is despawned along with any dying player, only the prompt and timer remain
+fn reset_player_death(
+    mut commands: Commands,
+    mut player_death: ResMut<PlayerDeath>,
+    prompt_query: Query<Entity, With<DeathPrompt>>,
+) {
+    player_death.timer = None;
+    for entity in prompt_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
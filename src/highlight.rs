@@ -0,0 +1,262 @@
+use crate::player::{
+    get_neighbor_direction, grab_from_held_input, handle_move_player_event,
+    ungrab_from_release_input, GlobalPlayerState, Grabbed, Player,
+};
+use crate::save::SaveData;
+use crate::tile_map::{EntityWalls, Pushable, GRID_SIZE};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct HighlightPlugin;
+
+// Highlights are layered on top of `BaseTint`, so whatever else colours a
+// sprite does so through its base tint and keeps its colour while highlighted.
+// Sprites are only written to when their highlight or base tint changes,
+// except for pulsing ones.
+impl Plugin for HighlightPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                insert_base_tints,
+                update_highlights
+                    .after(grab_from_held_input)
+                    .after(ungrab_from_release_input)
+                    .after(handle_move_player_event),
+                restyle_highlights,
+                apply_highlights
+                    .after(insert_base_tints)
+                    .after(update_highlights)
+                    .after(restyle_highlights),
+                pulse_highlights.after(apply_highlights),
+            )
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+const GRABBED_COLOR: Color = Color::rgb(0.4, 1.0, 0.4);
+const GRABBABLE_COLOR: Color = Color::rgb(1.0, 1.0, 0.6);
+/// Full pulses per second.
+const PULSE_SPEED: f32 = 1.5;
+/// The outline sprite is this many pixels larger on every side.
+const OUTLINE_WIDTH: f32 = 1.;
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HighlightStyle {
+    /// Multiplies the highlight colour into the sprite.
+    #[default]
+    Tint,
+    /// Fades between the base tint and the highlight colour.
+    Pulse,
+    /// Draws a coloured border behind the sprite and leaves the sprite alone.
+    Outline,
+}
+
+impl HighlightStyle {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HighlightStyle::Tint => "Tint",
+            HighlightStyle::Pulse => "Pulse",
+            HighlightStyle::Outline => "Outline",
+        }
+    }
+
+    pub fn cycle(&self, step: i32) -> HighlightStyle {
+        const STYLES: [HighlightStyle; 3] = [
+            HighlightStyle::Tint,
+            HighlightStyle::Pulse,
+            HighlightStyle::Outline,
+        ];
+        let index = STYLES.iter().position(|style| style == self).unwrap_or(0) as i32;
+        STYLES[(index + step).rem_euclid(STYLES.len() as i32) as usize]
+    }
+}
+
+/// The colour a sprite has without any highlight. Change this instead of the
+/// sprite colour to recolour a highlightable entity.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BaseTint(pub Color);
+
+#[derive(Component, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Highlight {
+    #[default]
+    None,
+    /// A player faces it and could grab it right now.
+    Grabbable,
+    Grabbed,
+}
+
+impl Highlight {
+    fn color(&self) -> Option<Color> {
+        match self {
+            Highlight::None => None,
+            Highlight::Grabbable => Some(GRABBABLE_COLOR),
+            Highlight::Grabbed => Some(GRABBED_COLOR),
+        }
+    }
+}
+
+#[derive(Component)]
+struct HighlightOutline;
+
+fn multiply(a: Color, b: Color) -> Color {
+    Color::rgba(a.r() * b.r(), a.g() * b.g(), a.b() * b.b(), a.a() * b.a())
+}
+
+fn lerp(a: Color, b: Color, amount: f32) -> Color {
+    let mix = |a: f32, b: f32| a + (b - a) * amount;
+    Color::rgba(
+        mix(a.r(), b.r()),
+        mix(a.g(), b.g()),
+        mix(a.b(), b.b()),
+        mix(a.a(), b.a()),
+    )
+}
+
+// whatever colour a sprite spawned with becomes its base tint
+fn insert_base_tints(
+    mut commands: Commands,
+    sprite_query: Query<
+        (Entity, &TextureAtlasSprite),
+        (Without<BaseTint>, Without<HighlightOutline>),
+    >,
+    pushable_query: Query<(), (With<Pushable>, Without<Highlight>)>,
+) {
+    for (entity, sprite) in sprite_query.iter() {
+        commands.entity(entity).insert(BaseTint(sprite.color));
+        if pushable_query.contains(entity) {
+            commands.entity(entity).insert(Highlight::None);
+        }
+    }
+}
+
+// neighbours are found in world space, a block across a level edge can be grabbed too
+fn update_highlights(
+    mut highlight_query: Query<(Entity, &GridCoords, &mut Highlight, Option<&Grabbed>)>,
+    player_query: Query<(Entity, &Player, &GridCoords)>,
+    walls: EntityWalls,
+    global_player_state: Res<GlobalPlayerState>,
+) {
+    for (entity, grid_coords, mut highlight, grabbed) in highlight_query.iter_mut() {
+        let world_coords = walls.of(entity).to_world(*grid_coords);
+        let wanted = if grabbed.is_some() {
+            Highlight::Grabbed
+        } else if !global_player_state.grabbing
+            && player_query
+                .iter()
+                .any(|(player_entity, player, player_grid_coords)| {
+                    let player_world_coords = walls.of(player_entity).to_world(*player_grid_coords);
+                    get_neighbor_direction(&player_world_coords, &world_coords)
+                        == player.face_direction()
+                })
+        {
+            Highlight::Grabbable
+        } else {
+            Highlight::None
+        };
+        // only flag a change when there is one
+        if *highlight != wanted {
+            *highlight = wanted;
+        }
+    }
+}
+
+fn restyle_highlights(
+    save_data: Res<SaveData>,
+    mut highlight_query: Query<&mut Highlight>,
+    mut style: Local<Option<HighlightStyle>>,
+) {
+    let current = save_data.settings.highlight_style;
+    if *style == Some(current) {
+        return;
+    }
+    let restyle = style.is_some();
+    *style = Some(current);
+    if restyle {
+        for mut highlight in highlight_query.iter_mut() {
+            highlight.set_changed();
+        }
+    }
+}
+
+fn apply_highlights(
+    mut commands: Commands,
+    save_data: Res<SaveData>,
+    mut sprite_query: Query<
+        (
+            Entity,
+            &BaseTint,
+            Option<&Highlight>,
+            &mut TextureAtlasSprite,
+            &Handle<TextureAtlas>,
+            Option<&Children>,
+        ),
+        Or<(Changed<BaseTint>, Changed<Highlight>)>,
+    >,
+    outline_query: Query<(), With<HighlightOutline>>,
+) {
+    let style = save_data.settings.highlight_style;
+    for (entity, base_tint, highlight, mut sprite, atlas, children) in sprite_query.iter_mut() {
+        let highlight_color = highlight.and_then(|highlight| highlight.color());
+        sprite.color = match (style, highlight_color) {
+            (HighlightStyle::Tint, Some(color)) => multiply(base_tint.0, color),
+            _ => base_tint.0,
+        };
+
+        if let Some(children) = children {
+            for child in children.iter() {
+                if outline_query.contains(*child) {
+                    commands.entity(*child).despawn_recursive();
+                }
+            }
+        }
+        if let (HighlightStyle::Outline, Some(color)) = (style, highlight_color) {
+            let outline = commands
+                .spawn((
+                    SpriteSheetBundle {
+                        sprite: TextureAtlasSprite {
+                            index: sprite.index,
+                            flip_x: sprite.flip_x,
+                            color,
+                            ..default()
+                        },
+                        texture_atlas: atlas.clone(),
+                        transform: Transform::from_xyz(0., 0., -0.1).with_scale(Vec3::new(
+                            (GRID_SIZE as f32 + OUTLINE_WIDTH * 2.) / GRID_SIZE as f32,
+                            (GRID_SIZE as f32 + OUTLINE_WIDTH * 2.) / GRID_SIZE as f32,
+                            1.,
+                        )),
+                        ..default()
+                    },
+                    HighlightOutline,
+                ))
+                .id();
+            commands.entity(entity).add_child(outline);
+        }
+    }
+}
+
+fn pulse_highlights(
+    time: Res<Time>,
+    save_data: Res<SaveData>,
+    mut sprite_query: Query<(&BaseTint, &Highlight, &mut TextureAtlasSprite)>,
+) {
+    if save_data.settings.highlight_style != HighlightStyle::Pulse {
+        return;
+    }
+    // reduced motion holds the pulse halfway instead of animating it
+    let amount = if save_data.settings.reduced_motion {
+        0.5
+    } else {
+        0.5 - 0.5 * (time.elapsed_seconds() * PULSE_SPEED * std::f32::consts::TAU).cos()
+    };
+    for (base_tint, highlight, mut sprite) in sprite_query.iter_mut() {
+        let Some(color) = highlight.color() else {
+            continue;
+        };
+        sprite.color = lerp(base_tint.0, multiply(base_tint.0, color), amount);
+    }
+}
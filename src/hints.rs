@@ -0,0 +1,432 @@
+use crate::actions::Actions;
+use crate::gravity::LevelRules;
+use crate::hazards::{Enemy, Spike};
+use crate::history::{MoveHistory, RestartEvent, TurnSnapshot, UndoEvent};
+use crate::lasers::{LaserEmitter, Receiver};
+use crate::pause::LeaveLevel;
+use crate::player::{
+    get_movement_coords_from_direction, GlobalPlayerState, Grabbed, Grabbing, Player, TurnEvent,
+};
+use crate::save::{SaveData, SaveFile};
+use crate::solver::{BlockState, PlayerState, Puzzle, PuzzleState, Solution, Step};
+use crate::terrain::Fragile;
+use crate::tile_map::{Block, EntityWalls, Goal, IsMoving, LevelWalls, GRID_SIZE};
+use crate::GameState;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_ecs_ldtk::prelude::*;
+use std::collections::HashMap;
+
+pub struct HintsPlugin;
+
+// Pressing H asks the solver for the next move from wherever the level is at.
+// The search runs on the async compute pool, so the game keeps going while it
+// thinks. The hint stays up until the players do something.
+impl Plugin for HintsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Hint>()
+            .init_resource::<HintTask>()
+            .add_systems(
+                Update,
+                (
+                    request_hint,
+                    clear_hint.before(request_hint),
+                    receive_hint.after(request_hint),
+                    show_hint.after(receive_hint),
+                    draw_hint.after(receive_hint),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(LeaveLevel, cleanup_hint);
+    }
+}
+
+const HINT_COLOR: Color = Color::rgb(1.0, 0.85, 0.2);
+
+#[derive(Default, Resource)]
+pub enum Hint {
+    #[default]
+    None,
+    /// The solver is still searching.
+    Thinking,
+    Step(Step),
+    /// Undoing this many turns gets back to a solvable state.
+    Undo(usize),
+    /// Not even the start of the attempt is solvable any more.
+    Restart,
+    TooHard,
+    Unsupported,
+}
+
+impl Hint {
+    fn message(&self) -> String {
+        match self {
+            Hint::None => String::new(),
+            Hint::Thinking => "Thinking about a hint...".to_string(),
+            Hint::Step(Step::Move(direction)) => format!("Hint: move {direction:?}"),
+            Hint::Step(Step::Grab) => "Hint: grab the highlighted block (hold Space)".to_string(),
+            Hint::Step(Step::Release) => "Hint: let go (release Space)".to_string(),
+            Hint::Undo(1) => "No solution from here \u{2014} undo 1 move".to_string(),
+            Hint::Undo(turns) => format!("No solution from here \u{2014} undo {turns} moves"),
+            Hint::Restart => "No solution from here \u{2014} restart the level".to_string(),
+            Hint::TooHard => "Couldn't work out a hint for this position".to_string(),
+            Hint::Unsupported => "No hints for this level".to_string(),
+        }
+    }
+
+    /// Whether the hint tells the players something, only those count as used.
+    fn helps(&self) -> bool {
+        matches!(self, Hint::Step(_) | Hint::Undo(_) | Hint::Restart)
+    }
+}
+
+/// The search started by the last hint request and the level it was asked
+/// in, dropping it cancels the search.
+#[derive(Default, Resource)]
+struct HintTask(Option<(String, Task<Hint>)>);
+
+#[derive(Component)]
+struct HintText;
+
+/// Everything the solver needs to know about the level, gathered from the ECS.
+#[derive(SystemParam)]
+pub struct PuzzleSource<'w, 's> {
+    player_query: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Player,
+            &'static GridCoords,
+            Option<&'static Grabbing>,
+        ),
+    >,
+    block_query: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static GridCoords,
+            Option<&'static Grabbed>,
+            Option<&'static Fragile>,
+        ),
+        Or<(With<Block>, With<Fragile>)>,
+    >,
+    goal_query: Query<'w, 's, (Entity, &'static GridCoords), With<Goal>>,
+    spike_query: Query<'w, 's, (Entity, &'static GridCoords), With<Spike>>,
+    unsupported_query: Query<'w, 's, Entity, Or<(With<Enemy>, With<LaserEmitter>, With<Receiver>)>>,
+    walls: EntityWalls<'w, 's>,
+    level_rules: Res<'w, LevelRules>,
+    global_player_state: Res<'w, GlobalPlayerState>,
+}
+
+impl<'w, 's> PuzzleSource<'w, 's> {
+    /// Levels with rules the solver doesn't model.
+    pub fn is_supported(&self) -> bool {
+        !self.level_rules.gravity
+            && !self
+                .unsupported_query
+                .iter()
+                .any(|entity| self.in_level(entity))
+    }
+
+    // the solver works on the level the players are in, in its grid coords
+    fn level_iid(&self) -> &str {
+        self.player_query
+            .iter()
+            .find_map(|(entity, ..)| self.walls.level_iid(entity))
+            .map_or("", |level_iid| level_iid.get())
+    }
+
+    fn in_level(&self, entity: Entity) -> bool {
+        self.walls
+            .level_iid(entity)
+            .is_some_and(|level_iid| level_iid.get() == self.level_iid())
+    }
+
+    pub fn puzzle(&self, level_walls: &LevelWalls) -> Puzzle {
+        Puzzle {
+            walls: level_walls.clone(),
+            goals: self
+                .goal_query
+                .iter()
+                .filter(|(entity, _)| self.in_level(*entity))
+                .map(|(_, grid_coords)| *grid_coords)
+                .collect(),
+            spikes: self
+                .spike_query
+                .iter()
+                .filter(|(entity, _)| self.in_level(*entity))
+                .map(|(_, grid_coords)| *grid_coords)
+                .collect(),
+            push_limit: self.level_rules.push_limit,
+        }
+    }
+
+    /// The level as it is right now.
+    pub fn current(&self) -> (Puzzle, PuzzleState) {
+        let players = self
+            .player_query
+            .iter()
+            .filter(|(entity, ..)| self.in_level(*entity))
+            .map(|(_, player, grid_coords, grabbing)| {
+                PlayerState::new(*grid_coords, player.face_direction(), grabbing.is_some())
+            })
+            .collect();
+        let blocks = self
+            .block_query
+            .iter()
+            .filter(|(entity, ..)| self.in_level(*entity))
+            .map(|(_, grid_coords, grabbed, fragile)| BlockState {
+                grid_coords: *grid_coords,
+                grabbed: grabbed.is_some(),
+                pushes_left: fragile.map(|fragile| fragile.pushes_left()),
+            })
+            .collect();
+        let grab_direction = self
+            .global_player_state
+            .grabbing
+            .then_some(self.global_player_state.direction);
+        (
+            self.puzzle(self.walls.world().get(self.level_iid())),
+            PuzzleState::new(players, blocks, grab_direction),
+        )
+    }
+
+    /// The level as it was before a recorded turn. Undo lets go of any grab,
+    /// and facings aren't recorded, so the current ones are kept.
+    pub fn before(&self, snapshot: &TurnSnapshot) -> (Puzzle, PuzzleState) {
+        let positions: HashMap<Entity, GridCoords> = snapshot.positions.iter().copied().collect();
+        let pushes_left: HashMap<Entity, i32> = snapshot
+            .fragile_blocks
+            .iter()
+            .map(|(entity, fragile)| (*entity, fragile.pushes_left()))
+            .collect();
+        let players = self
+            .player_query
+            .iter()
+            .filter(|(entity, ..)| self.in_level(*entity))
+            .map(|(entity, player, grid_coords, _)| {
+                PlayerState::new(
+                    positions.get(&entity).copied().unwrap_or(*grid_coords),
+                    player.face_direction(),
+                    false,
+                )
+            })
+            .collect();
+        let blocks = self
+            .block_query
+            .iter()
+            .filter(|(entity, ..)| self.in_level(*entity))
+            .map(|(entity, grid_coords, _, fragile)| BlockState {
+                grid_coords: positions.get(&entity).copied().unwrap_or(*grid_coords),
+                grabbed: false,
+                pushes_left: fragile.map(|fragile| {
+                    pushes_left
+                        .get(&entity)
+                        .copied()
+                        .unwrap_or(fragile.pushes_left())
+                }),
+            })
+            .collect();
+        (
+            self.puzzle(snapshot.walls.get(self.level_iid())),
+            PuzzleState::new(players, blocks, None),
+        )
+    }
+}
+
+// `earlier` holds the level before each recorded turn, latest turn first
+fn find_hint(current: (Puzzle, PuzzleState), earlier: Vec<(Puzzle, PuzzleState)>) -> Hint {
+    let (puzzle, state) = current;
+    match puzzle.solve(&state) {
+        Solution::Solved(steps) => {
+            return steps.first().map_or(Hint::None, |step| Hint::Step(*step))
+        }
+        Solution::TooHard => return Hint::TooHard,
+        Solution::Unsolvable => {}
+    }
+    // walk back through the history until a solvable turn turns up
+    for (undos, (puzzle, state)) in earlier.into_iter().enumerate() {
+        match puzzle.solve(&state) {
+            Solution::Solved(_) => return Hint::Undo(undos + 1),
+            Solution::TooHard => return Hint::TooHard,
+            Solution::Unsolvable => {}
+        }
+    }
+    Hint::Restart
+}
+
+fn request_hint(
+    actions: Res<Actions>,
+    moving_query: Query<(), With<IsMoving>>,
+    puzzle_source: PuzzleSource,
+    history: Res<MoveHistory>,
+    mut hint: ResMut<Hint>,
+    mut hint_task: ResMut<HintTask>,
+) {
+    if !actions.hint || !moving_query.is_empty() || hint_task.0.is_some() {
+        return;
+    }
+    if puzzle_source.is_supported() {
+        let current = puzzle_source.current();
+        let earlier = history
+            .turns()
+            .iter()
+            .rev()
+            .map(|snapshot| puzzle_source.before(snapshot))
+            .collect();
+        let task = AsyncComputeTaskPool::get().spawn(async move { find_hint(current, earlier) });
+        hint_task.0 = Some((puzzle_source.level_iid().to_string(), task));
+        *hint = Hint::Thinking;
+    } else {
+        *hint = Hint::Unsupported;
+    }
+}
+
+// only hints that made it to the screen and said something count as used
+fn receive_hint(
+    mut hint_task: ResMut<HintTask>,
+    mut hint: ResMut<Hint>,
+    mut save_data: ResMut<SaveData>,
+    save_file: Res<SaveFile>,
+) {
+    if !hint_task
+        .0
+        .as_ref()
+        .is_some_and(|(_, task)| task.is_finished())
+    {
+        return;
+    }
+    let Some((level_iid, task)) = hint_task.0.take() else {
+        return;
+    };
+    *hint = block_on(task);
+    if hint.helps() && !level_iid.is_empty() {
+        save_data.record_hint(&level_iid);
+        if let Err(error) = save_data.store(&save_file) {
+            warn!("Failed to write save file {:?}: {error}", save_file.path);
+        }
+    }
+}
+
+// any input that changes the level makes the hint stale
+fn clear_hint(
+    actions: Res<Actions>,
+    mut turns: EventReader<TurnEvent>,
+    mut ev_undo: EventReader<UndoEvent>,
+    mut ev_restart: EventReader<RestartEvent>,
+    mut hint: ResMut<Hint>,
+    mut hint_task: ResMut<HintTask>,
+) {
+    let changed = turns.iter().count() + ev_undo.iter().count() + ev_restart.iter().count() > 0
+        || actions.grab_pressed
+        || actions.grab_released;
+    if !changed {
+        return;
+    }
+    hint_task.0 = None;
+    if !matches!(*hint, Hint::None) {
+        *hint = Hint::None;
+    }
+}
+
+fn show_hint(
+    mut commands: Commands,
+    hint: Res<Hint>,
+    mut text_query: Query<(Entity, &mut Text), With<HintText>>,
+) {
+    if !hint.is_changed() {
+        return;
+    }
+    let message = hint.message();
+    if let Ok((entity, mut text)) = text_query.get_single_mut() {
+        if message.is_empty() {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            text.sections[0].value = message;
+        }
+        return;
+    }
+    if message.is_empty() {
+        return;
+    }
+    commands.spawn((
+        TextBundle::from_section(
+            message,
+            TextStyle {
+                font_size: 20.0,
+                color: HINT_COLOR,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(40.),
+            left: Val::Px(10.),
+            ..default()
+        }),
+        HintText,
+    ));
+}
+
+// arrows from the players for moves, rings around the blocks to grab
+fn draw_hint(
+    mut gizmos: Gizmos,
+    hint: Res<Hint>,
+    player_query: Query<(&Player, &GridCoords, &GlobalTransform)>,
+    block_query: Query<(&GridCoords, &GlobalTransform), With<Block>>,
+) {
+    let Hint::Step(step) = *hint else {
+        return;
+    };
+    let cell = GRID_SIZE as f32;
+    match step {
+        Step::Move(direction) => {
+            let Some(offset) = get_movement_coords_from_direction(direction) else {
+                return;
+            };
+            let offset = Vec2::new(offset.x as f32, offset.y as f32);
+            for (_, _, transform) in player_query.iter() {
+                let start = transform.translation().truncate() + offset * cell * 0.3;
+                let end = start + offset * cell * 0.5;
+                let side = Vec2::new(-offset.y, offset.x) * cell * 0.2;
+                gizmos.line_2d(start, end, HINT_COLOR);
+                gizmos.line_2d(end, end - offset * cell * 0.2 + side, HINT_COLOR);
+                gizmos.line_2d(end, end - offset * cell * 0.2 - side, HINT_COLOR);
+            }
+        }
+        Step::Grab => {
+            for (player, player_grid_coords, _) in player_query.iter() {
+                let Some(offset) = get_movement_coords_from_direction(player.face_direction())
+                else {
+                    continue;
+                };
+                for (block_grid_coords, transform) in block_query.iter() {
+                    if *player_grid_coords + offset == *block_grid_coords {
+                        gizmos.circle_2d(
+                            transform.translation().truncate(),
+                            cell * 0.6,
+                            HINT_COLOR,
+                        );
+                    }
+                }
+            }
+        }
+        Step::Release => {}
+    }
+}
+
+fn cleanup_hint(
+    mut commands: Commands,
+    text_query: Query<Entity, With<HintText>>,
+    mut hint: ResMut<Hint>,
+    mut hint_task: ResMut<HintTask>,
+) {
+    *hint = Hint::None;
+    hint_task.0 = None;
+    for entity in text_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,184 @@
+use crate::actions::Actions;
+use crate::hazards::Enemy;
+use crate::player::{
+    handle_move_player, handle_move_player_event, GlobalPlayerState, Grabbed, Grabbing, Movable,
+    TurnEvent,
+};
+use crate::score::MoveCounter;
+use crate::terrain::Fragile;
+use crate::tile_map::{IsMoving, WorldWalls};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MoveHistory>()
+            .add_event::<UndoEvent>()
+            .add_event::<RestartEvent>()
+            .add_systems(
+                Update,
+                (
+                    undo_from_input,
+                    restart_from_input,
+                    record_turn
+                        .after(handle_move_player)
+                        .before(handle_move_player_event),
+                    undo_turn.after(undo_from_input),
+                    restart_level.after(restart_from_input),
+                    clear_history,
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Request to roll the level back by one turn.
+#[derive(Event)]
+pub struct UndoEvent;
+
+/// Request to respawn the current level from its LDtk data.
+#[derive(Event)]
+pub struct RestartEvent;
+
+/// Everything needed to put the level back the way it was before a turn.
+pub struct TurnSnapshot {
+    pub positions: Vec<(Entity, GridCoords)>,
+    pub enemies: Vec<(Entity, Enemy)>,
+    pub fragile_blocks: Vec<(Entity, Fragile)>,
+    pub walls: WorldWalls,
+    pub move_counter: MoveCounter,
+}
+
+#[derive(Default, Resource)]
+pub struct MoveHistory {
+    turns: Vec<TurnSnapshot>,
+}
+
+impl MoveHistory {
+    pub fn len(&self) -> usize {
+        self.turns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    /// Snapshots from the oldest turn to the latest one.
+    pub fn turns(&self) -> &[TurnSnapshot] {
+        &self.turns
+    }
+}
+
+pub fn undo_from_input(actions: Res<Actions>, mut ev_undo: EventWriter<UndoEvent>) {
+    if actions.undo {
+        ev_undo.send(UndoEvent);
+    }
+}
+
+pub fn restart_from_input(actions: Res<Actions>, mut ev_restart: EventWriter<RestartEvent>) {
+    if actions.restart {
+        ev_restart.send(RestartEvent);
+    }
+}
+
+// snapshots are taken after the players decided to move
+// but before their grid coords are actually changed
+pub fn record_turn(
+    mut turns: EventReader<TurnEvent>,
+    mut history: ResMut<MoveHistory>,
+    movable_query: Query<(Entity, &GridCoords), With<Movable>>,
+    enemy_query: Query<(Entity, &Enemy)>,
+    fragile_query: Query<(Entity, &Fragile)>,
+    world_walls: Res<WorldWalls>,
+    move_counter: Res<MoveCounter>,
+) {
+    for _ in turns.iter() {
+        history.turns.push(TurnSnapshot {
+            positions: movable_query
+                .iter()
+                .map(|(entity, grid_coords)| (entity, *grid_coords))
+                .collect(),
+            enemies: enemy_query
+                .iter()
+                .map(|(entity, enemy)| (entity, enemy.clone()))
+                .collect(),
+            fragile_blocks: fragile_query
+                .iter()
+                .map(|(entity, fragile)| (entity, fragile.clone()))
+                .collect(),
+            walls: world_walls.clone(),
+            move_counter: *move_counter,
+        });
+    }
+}
+
+pub fn undo_turn(
+    mut commands: Commands,
+    mut ev_undo: EventReader<UndoEvent>,
+    mut history: ResMut<MoveHistory>,
+    mut grid_coords_query: Query<&mut GridCoords>,
+    mut enemy_query: Query<&mut Enemy>,
+    mut fragile_query: Query<&mut Fragile>,
+    mut world_walls: ResMut<WorldWalls>,
+    mut move_counter: ResMut<MoveCounter>,
+    movable_query: Query<Entity, With<Movable>>,
+    mut global_player_state: ResMut<GlobalPlayerState>,
+) {
+    for _ in ev_undo.iter() {
+        let Some(snapshot) = history.turns.pop() else {
+            continue;
+        };
+        for (entity, grid_coords) in snapshot.positions {
+            if let Ok(mut current) = grid_coords_query.get_mut(entity) {
+                if *current != grid_coords {
+                    *current = grid_coords;
+                }
+            }
+        }
+        for (entity, enemy) in snapshot.enemies {
+            if let Ok(mut current) = enemy_query.get_mut(entity) {
+                *current = enemy;
+            }
+        }
+        for (entity, fragile) in snapshot.fragile_blocks {
+            if let Ok(mut current) = fragile_query.get_mut(entity) {
+                *current = fragile;
+            }
+        }
+        *world_walls = snapshot.walls;
+        *move_counter = snapshot.move_counter;
+        // grabs are not part of the history, so let go of everything
+        for entity in movable_query.iter() {
+            commands
+                .entity(entity)
+                .remove::<(Grabbed, Grabbing, IsMoving)>();
+        }
+        global_player_state.grabbing = false;
+    }
+}
+
+pub fn restart_level(
+    mut commands: Commands,
+    mut ev_restart: EventReader<RestartEvent>,
+    levels: Query<Entity, With<LevelIid>>,
+    mut global_player_state: ResMut<GlobalPlayerState>,
+) {
+    if ev_restart.iter().count() == 0 {
+        return;
+    }
+    for level_entity in levels.iter() {
+        commands.entity(level_entity).insert(Respawn);
+    }
+    *global_player_state = GlobalPlayerState::default();
+}
+
+pub fn clear_history(mut history: ResMut<MoveHistory>, mut level_events: EventReader<LevelEvent>) {
+    for level_event in level_events.iter() {
+        if let LevelEvent::Spawned(_) = level_event {
+            history.turns.clear();
+        }
+    }
+}
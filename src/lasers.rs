@@ -0,0 +1,292 @@
+use crate::hazards::{check_player_hazards, move_enemies, PlayerDeath, PlayerDied};
+use crate::player::{get_movement_coords_from_direction, Direction, Movable, Player};
+use crate::tile_map::{
+    Block, EntityWalls, LevelMembership, Pullable, Pushable, Tile, WorldWalls, GRID_SIZE,
+};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+pub struct LasersPlugin;
+
+impl Plugin for LasersPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LaserBeams>()
+            .register_ldtk_entity::<LaserEmitterBundle>("LaserEmitter")
+            .register_ldtk_entity::<MirrorBundle>("Mirror")
+            .register_ldtk_entity::<ReceiverBundle>("Receiver")
+            .register_ldtk_entity::<DoorBundle>("LaserDoor")
+            .add_systems(
+                Update,
+                (
+                    update_laser_beams
+                        .after(move_enemies)
+                        .before(check_player_hazards),
+                    update_doors.after(update_laser_beams),
+                    draw_laser_beams.after(update_laser_beams),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+const BEAM_COLOR: Color = Color::rgb(1.0, 0.2, 0.2);
+
+fn parse_direction(value: &str) -> Direction {
+    match value {
+        "North" => Direction::North,
+        "South" => Direction::South,
+        "West" => Direction::West,
+        _ => Direction::East,
+    }
+}
+
+#[derive(Default, Component)]
+pub struct LaserEmitter {
+    direction: Direction,
+    lethal: bool,
+}
+
+impl From<&EntityInstance> for LaserEmitter {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        LaserEmitter {
+            direction: entity_instance
+                .get_enum_field("direction")
+                .map(|direction| parse_direction(direction.as_str()))
+                .unwrap_or_default(),
+            lethal: *entity_instance.get_bool_field("lethal").unwrap_or(&false),
+        }
+    }
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct LaserEmitterBundle {
+    #[from_entity_instance]
+    emitter: LaserEmitter,
+    #[sprite_sheet_bundle]
+    sprite_bundle: SpriteSheetBundle,
+    #[grid_coords]
+    grid_coords: GridCoords,
+}
+
+/// A mirror deflecting beams by 90°. `Slash` mirrors look like `/`,
+/// `Backslash` mirrors like `\`.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Component)]
+pub enum Mirror {
+    #[default]
+    Slash,
+    Backslash,
+}
+
+impl From<&EntityInstance> for Mirror {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        match entity_instance.get_enum_field("orientation") {
+            Ok(orientation) if orientation == "Backslash" => Mirror::Backslash,
+            _ => Mirror::Slash,
+        }
+    }
+}
+
+impl Mirror {
+    fn deflect(&self, direction: Direction) -> Direction {
+        match (self, direction) {
+            (Mirror::Slash, Direction::North) => Direction::East,
+            (Mirror::Slash, Direction::East) => Direction::North,
+            (Mirror::Slash, Direction::South) => Direction::West,
+            (Mirror::Slash, Direction::West) => Direction::South,
+            (Mirror::Backslash, Direction::North) => Direction::West,
+            (Mirror::Backslash, Direction::West) => Direction::North,
+            (Mirror::Backslash, Direction::South) => Direction::East,
+            (Mirror::Backslash, Direction::East) => Direction::South,
+            (_, direction) => direction,
+        }
+    }
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct MirrorBundle {
+    block: Block,
+    movable: Movable,
+    pushable: Pushable,
+    pullable: Pullable,
+    #[from_entity_instance]
+    mirror: Mirror,
+    #[sprite_sheet_bundle]
+    sprite_bundle: SpriteSheetBundle,
+    #[grid_coords]
+    grid_coords: GridCoords,
+}
+
+#[derive(Default, Component)]
+pub struct Receiver {
+    pub lit: bool,
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct ReceiverBundle {
+    receiver: Receiver,
+    #[sprite_sheet_bundle]
+    sprite_bundle: SpriteSheetBundle,
+    #[grid_coords]
+    grid_coords: GridCoords,
+}
+
+/// Doors stay closed until every receiver in their level is lit. A level
+/// without receivers keeps its doors shut. They are `LaserDoor`s in LDtk, plain
+/// `Door`s belong to the platformer and the hub.
+#[derive(Default, Component)]
+pub struct Door;
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct DoorBundle {
+    door: Door,
+    #[sprite_sheet_bundle]
+    sprite_bundle: SpriteSheetBundle,
+    #[grid_coords]
+    grid_coords: GridCoords,
+}
+
+/// Beam segments from the last recomputation, as start and end cells in world-space
+/// grid coords so beams crossing into a neighbouring level line up.
+#[derive(Default, Resource)]
+pub struct LaserBeams {
+    segments: Vec<(GridCoords, GridCoords)>,
+}
+
+// everything is compared in world space, an emitter's beam may reach into
+// the level next to it
+pub fn update_laser_beams(
+    emitter_query: Query<(Entity, &LaserEmitter, &GridCoords)>,
+    mirror_query: Query<(Entity, &Mirror, &GridCoords), With<Block>>,
+    block_query: Query<(Entity, &GridCoords), (With<Block>, Without<Mirror>)>,
+    mut receiver_query: Query<(Entity, &mut Receiver, &GridCoords)>,
+    player_query: Query<(Entity, &GridCoords), With<Player>>,
+    changed_query: Query<(), Changed<GridCoords>>,
+    walls: EntityWalls,
+    player_death: Res<PlayerDeath>,
+    mut laser_beams: ResMut<LaserBeams>,
+    mut ev_player_died: EventWriter<PlayerDied>,
+) {
+    if changed_query.is_empty() && !walls.is_changed() {
+        return;
+    }
+    laser_beams.segments.clear();
+    let to_world =
+        |entity: Entity, grid_coords: &GridCoords| walls.of(entity).to_world(*grid_coords);
+    let mirrors: HashMap<GridCoords, &Mirror> = mirror_query
+        .iter()
+        .map(|(entity, mirror, grid_coords)| (to_world(entity, grid_coords), mirror))
+        .collect();
+    let blocks: HashSet<GridCoords> = block_query
+        .iter()
+        .map(|(entity, grid_coords)| to_world(entity, grid_coords))
+        .collect();
+    let receivers: HashSet<GridCoords> = receiver_query
+        .iter()
+        .map(|(entity, _, grid_coords)| to_world(entity, grid_coords))
+        .collect();
+    let players: HashMap<GridCoords, Entity> = player_query
+        .iter()
+        .map(|(entity, grid_coords)| (to_world(entity, grid_coords), entity))
+        .collect();
+    let mut lit_receivers = HashSet::new();
+
+    for (emitter_entity, emitter, emitter_grid_coords) in emitter_query.iter() {
+        // a beam bouncing between mirrors can't visit more cells than this
+        let level_walls = walls.of(emitter_entity);
+        let max_steps = (level_walls.width() * level_walls.height() * 4).max(1);
+        let mut direction = emitter.direction;
+        // walls are looked up from the emitter's level, the rest in world space
+        let mut current = *emitter_grid_coords;
+        let mut segment_start = level_walls.to_world(current);
+        for _ in 0..max_steps {
+            let Some(step) = get_movement_coords_from_direction(direction) else {
+                break;
+            };
+            let next = current + step;
+            if walls.in_wall(emitter_entity, &next) || blocks.contains(&level_walls.to_world(next))
+            {
+                break;
+            }
+            current = next;
+            let world_current = level_walls.to_world(current);
+            if let Some(mirror) = mirrors.get(&world_current) {
+                laser_beams.segments.push((segment_start, world_current));
+                segment_start = world_current;
+                direction = mirror.deflect(direction);
+                continue;
+            }
+            if receivers.contains(&world_current) {
+                lit_receivers.insert(world_current);
+                break;
+            }
+            if let Some(player) = players.get(&world_current) {
+                if emitter.lethal && !player_death.is_dead() {
+                    ev_player_died.send(PlayerDied(*player));
+                }
+                break;
+            }
+        }
+        laser_beams
+            .segments
+            .push((segment_start, level_walls.to_world(current)));
+    }
+
+    for (entity, mut receiver, receiver_grid_coords) in receiver_query.iter_mut() {
+        let lit = lit_receivers.contains(&walls.of(entity).to_world(*receiver_grid_coords));
+        if receiver.lit != lit {
+            receiver.lit = lit;
+        }
+    }
+}
+
+pub fn update_doors(
+    receiver_query: Query<(Entity, &Receiver)>,
+    mut door_query: Query<(Entity, &GridCoords, &mut Visibility), With<Door>>,
+    membership: LevelMembership,
+    mut world_walls: ResMut<WorldWalls>,
+) {
+    // whether every receiver of a level is lit, for levels with at least one
+    let mut levels_lit: HashMap<String, bool> = HashMap::new();
+    for (entity, receiver) in receiver_query.iter() {
+        if let Some(level_iid) = membership.level_iid(entity) {
+            *levels_lit.entry(level_iid.get().clone()).or_insert(true) &= receiver.lit;
+        }
+    }
+    for (entity, grid_coords, mut visibility) in door_query.iter_mut() {
+        let level_iid = membership.level_iid(entity);
+        let open = level_iid
+            .and_then(|level_iid| levels_lit.get(level_iid.get()))
+            .copied()
+            .unwrap_or(false);
+        let tile = if open { Tile::Floor } else { Tile::ClosedDoor };
+        if let Some(level_iid) = level_iid {
+            if world_walls.get(level_iid.get()).tile(grid_coords) != Some(tile) {
+                if let Some(level_walls) = world_walls.get_mut(level_iid.get()) {
+                    level_walls.set_tile(grid_coords, tile);
+                }
+            }
+        }
+        let new_visibility = if open {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        if *visibility != new_visibility {
+            *visibility = new_visibility;
+        }
+    }
+}
+
+// levels are spawned at their LDtk world positions, so world-space grid coords
+// translate straight to the world
+pub fn draw_laser_beams(mut gizmos: Gizmos, laser_beams: Res<LaserBeams>) {
+    for (start, end) in laser_beams.segments.iter() {
+        gizmos.line_2d(
+            bevy_ecs_ldtk::utils::grid_coords_to_translation(*start, IVec2::splat(GRID_SIZE)),
+            bevy_ecs_ldtk::utils::grid_coords_to_translation(*end, IVec2::splat(GRID_SIZE)),
+            BEAM_COLOR,
+        );
+    }
+}
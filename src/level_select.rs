@@ -0,0 +1,287 @@
+use crate::loading::LevelAssets;
+use crate::menu::ButtonColors;
+use crate::progress::{is_level_unlocked, is_playable};
+use crate::save::SaveData;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+pub struct LevelSelectPlugin;
+
+impl Plugin for LevelSelectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelSelectFocus>()
+            .add_systems(OnEnter(GameState::LevelSelect), setup_level_select)
+            .add_systems(
+                Update,
+                (
+                    build_level_buttons,
+                    navigate_level_select.after(build_level_buttons),
+                    click_level_button.after(build_level_buttons),
+                    update_level_button_colors
+                        .after(navigate_level_select)
+                        .after(click_level_button),
+                )
+                    .run_if(in_state(GameState::LevelSelect)),
+            )
+            .add_systems(OnExit(GameState::LevelSelect), cleanup_level_select);
+    }
+}
+
+const COLUMNS: usize = 4;
+const LOCKED_COLOR: Color = Color::rgb(0.1, 0.1, 0.1);
+
+#[derive(Default, Resource)]
+pub struct LevelSelectFocus(pub usize);
+
+#[derive(Component)]
+struct LevelSelect;
+
+#[derive(Component)]
+struct LevelGrid(Vec<Handle<LdtkProject>>);
+
+#[derive(Component)]
+struct LevelButton {
+    index: usize,
+    level_iid: String,
+    unlocked: bool,
+}
+
+fn setup_level_select(
+    mut commands: Commands,
+    level_assets: Res<LevelAssets>,
+    mut focus: ResMut<LevelSelectFocus>,
+) {
+    focus.0 = 0;
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            LevelSelect,
+        ))
+        .with_children(|children| {
+            children.spawn(TextBundle::from_section(
+                "Select Level",
+                TextStyle {
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+            children.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Px(150.0 * COLUMNS as f32),
+                        flex_direction: FlexDirection::Row,
+                        flex_wrap: FlexWrap::Wrap,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    ..default()
+                },
+                LevelGrid(level_assets.projects().into_iter().cloned().collect()),
+            ));
+        });
+}
+
+// the buttons can only be built once the projects have been loaded
+fn build_level_buttons(
+    mut commands: Commands,
+    grid_query: Query<(Entity, &LevelGrid), Without<Children>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    save_data: Res<SaveData>,
+) {
+    for (grid_entity, grid) in grid_query.iter() {
+        let Some(ldtk_projects) = grid
+            .0
+            .iter()
+            .map(|handle| ldtk_project_assets.get(handle))
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        // the first level of every project is open from the start
+        let levels = ldtk_projects.into_iter().flat_map(|ldtk_project| {
+            ldtk_project
+                .iter_raw_levels()
+                .filter(|level| is_playable(level))
+                .enumerate()
+        });
+        commands.entity(grid_entity).with_children(|children| {
+            for (index, (project_index, level)) in levels.enumerate() {
+                let unlocked = is_level_unlocked(
+                    project_index == 0,
+                    &level.iid,
+                    level
+                        .neighbours
+                        .iter()
+                        .map(|neighbour| neighbour.level_iid.as_str()),
+                    &save_data,
+                );
+                let status = if !unlocked {
+                    "Locked".to_string()
+                } else if let Some(best_moves) = save_data
+                    .record(&level.iid)
+                    .and_then(|record| record.best_moves)
+                {
+                    format!("Best: {best_moves} moves")
+                } else {
+                    "Not completed".to_string()
+                };
+                let button_colors = ButtonColors::default();
+                children
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(140.0),
+                                height: Val::Px(70.0),
+                                margin: UiRect::all(Val::Px(5.)),
+                                flex_direction: FlexDirection::Column,
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: button_colors.normal.into(),
+                            ..default()
+                        },
+                        button_colors,
+                        LevelButton {
+                            index,
+                            level_iid: level.iid.clone(),
+                            unlocked,
+                        },
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            level.identifier.clone(),
+                            TextStyle {
+                                font_size: 20.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ));
+                        parent.spawn(TextBundle::from_section(
+                            status,
+                            TextStyle {
+                                font_size: 15.0,
+                                color: Color::rgb(0.7, 0.7, 0.7),
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+    }
+}
+
+fn select_level(
+    button: &LevelButton,
+    level_selection: &mut LevelSelection,
+    next_state: &mut NextState<GameState>,
+) {
+    if !button.unlocked {
+        return;
+    }
+    *level_selection = LevelSelection::iid(button.level_iid.clone());
+    next_state.set(GameState::Playing);
+}
+
+fn navigate_level_select(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    button_query: Query<&LevelButton>,
+    mut focus: ResMut<LevelSelectFocus>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let count = button_query.iter().count();
+    if count == 0 {
+        return;
+    }
+    let gamepad_pressed = |button_type| {
+        gamepads
+            .iter()
+            .any(|gamepad| gamepad_input.just_pressed(GamepadButton::new(gamepad, button_type)))
+    };
+
+    if keyboard_input.any_just_pressed([KeyCode::A, KeyCode::Left])
+        || gamepad_pressed(GamepadButtonType::DPadLeft)
+    {
+        focus.0 = focus.0.saturating_sub(1);
+    } else if keyboard_input.any_just_pressed([KeyCode::D, KeyCode::Right])
+        || gamepad_pressed(GamepadButtonType::DPadRight)
+    {
+        focus.0 = (focus.0 + 1).min(count - 1);
+    } else if keyboard_input.any_just_pressed([KeyCode::W, KeyCode::Up])
+        || gamepad_pressed(GamepadButtonType::DPadUp)
+    {
+        focus.0 = focus.0.saturating_sub(COLUMNS);
+    } else if keyboard_input.any_just_pressed([KeyCode::S, KeyCode::Down])
+        || gamepad_pressed(GamepadButtonType::DPadDown)
+    {
+        focus.0 = (focus.0 + COLUMNS).min(count - 1);
+    } else if keyboard_input.any_just_pressed([KeyCode::Return, KeyCode::Space])
+        || gamepad_pressed(GamepadButtonType::South)
+    {
+        if let Some(button) = button_query.iter().find(|button| button.index == focus.0) {
+            select_level(button, &mut level_selection, &mut next_state);
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Escape)
+        || gamepad_pressed(GamepadButtonType::East)
+    {
+        next_state.set(GameState::Menu);
+    }
+}
+
+// mouse and touch both go through ui interactions
+fn click_level_button(
+    interaction_query: Query<(&Interaction, &LevelButton), Changed<Interaction>>,
+    mut focus: ResMut<LevelSelectFocus>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        match *interaction {
+            Interaction::Pressed => {
+                focus.0 = button.index;
+                select_level(button, &mut level_selection, &mut next_state);
+            }
+            Interaction::Hovered => focus.0 = button.index,
+            Interaction::None => {}
+        }
+    }
+}
+
+fn update_level_button_colors(
+    focus: Res<LevelSelectFocus>,
+    mut button_query: Query<(&LevelButton, &ButtonColors, &mut BackgroundColor)>,
+) {
+    for (button, button_colors, mut color) in button_query.iter_mut() {
+        let new_color = if button.index == focus.0 {
+            button_colors.hovered
+        } else if button.unlocked {
+            button_colors.normal
+        } else {
+            LOCKED_COLOR
+        };
+        if color.0 != new_color {
+            color.0 = new_color;
+        }
+    }
+}
+
+fn cleanup_level_select(mut commands: Commands, query: Query<Entity, With<LevelSelect>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,13 +1,62 @@
 #![allow(clippy::type_complexity)]
 
 mod actions;
+mod animation;
+mod audio;
+mod camera;
+mod deadlock;
+mod error;
+mod feedback;
+mod gravity;
+mod hazards;
+mod highlight;
+mod hints;
+mod history;
+mod lasers;
+mod level_select;
 mod loading;
 mod menu;
+mod occupancy;
+mod overworld;
+mod pause;
 mod player;
+mod preview;
+mod progress;
+mod replay;
+mod results;
+mod save;
+mod score;
+mod settings;
+mod solver;
+mod terrain;
 mod tile_map;
 
+use crate::actions::ActionsPlugin;
+use crate::animation::AnimationPlugin;
+use crate::audio::InternalAudioPlugin;
+use crate::camera::CameraPlugin;
+use crate::deadlock::DeadlockPlugin;
+use crate::error::ErrorPlugin;
+use crate::feedback::FeedbackPlugin;
+use crate::gravity::GravityPlugin;
+use crate::hazards::HazardsPlugin;
+use crate::highlight::HighlightPlugin;
+use crate::hints::HintsPlugin;
+use crate::history::HistoryPlugin;
+use crate::lasers::LasersPlugin;
+use crate::level_select::LevelSelectPlugin;
 use crate::loading::LoadingPlugin;
 use crate::menu::MenuPlugin;
+use crate::occupancy::OccupancyPlugin;
+use crate::overworld::OverworldPlugin;
+use crate::pause::PausePlugin;
+use crate::preview::PreviewPlugin;
+use crate::progress::ProgressPlugin;
+use crate::replay::ReplayPlugin;
+use crate::results::ResultsPlugin;
+use crate::save::SavePlugin;
+use crate::score::ScorePlugin;
+use crate::settings::SettingsPlugin;
 
 use bevy::app::App;
 #[cfg(debug_assertions)]
@@ -17,29 +66,79 @@ use bevy_ecs_ldtk::prelude::*;
 use bevy_kira_audio::AudioPlugin;
 use bevy_tweening::TweeningPlugin;
 use player::PlayerPlugin;
+use terrain::TerrainPlugin;
 use tile_map::TilemapPlugin;
 
+pub use replay::ReplayFile;
+
+/// What `benches/occupancy.rs` needs to run the occupancy index and the
+/// movement rules outside the game.
+#[cfg(feature = "bench")]
+pub mod bench_support {
+    pub use crate::gravity::LevelRules;
+    pub use crate::occupancy::{update_occupancy, Occupancy};
+    pub use crate::player::{Direction, GlobalPlayerState, Movable, MoveRules, Player};
+    pub use crate::tile_map::{Block, LevelWalls, Pullable, Pushable, Tile, WorldWalls};
+}
+
 #[derive(States, Default, Clone, Eq, PartialEq, Debug, Hash)]
 enum GameState {
     #[default]
     Loading,
     Playing,
     Menu,
+    Overworld,
+    LevelSelect,
+    Paused,
+    Settings,
+    LevelComplete,
+    LoadingFailed,
+    Error,
 }
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_state::<GameState>().add_plugins((
-            LdtkPlugin,
-            LoadingPlugin,
-            MenuPlugin,
-            TilemapPlugin,
-            PlayerPlugin,
-            TweeningPlugin,
-            AudioPlugin,
-        ));
+        app.add_state::<GameState>()
+            .add_plugins((
+                LdtkPlugin,
+                TweeningPlugin,
+                AudioPlugin,
+                LoadingPlugin,
+                ErrorPlugin,
+                SavePlugin,
+                MenuPlugin,
+                LevelSelectPlugin,
+                OverworldPlugin,
+            ))
+            .add_plugins((
+                TilemapPlugin,
+                PlayerPlugin,
+                HazardsPlugin,
+                HistoryPlugin,
+                TerrainPlugin,
+                LasersPlugin,
+                GravityPlugin,
+                ProgressPlugin,
+                ScorePlugin,
+                ResultsPlugin,
+                OccupancyPlugin,
+            ))
+            .add_plugins((
+                ActionsPlugin,
+                ReplayPlugin,
+                PausePlugin,
+                SettingsPlugin,
+                InternalAudioPlugin,
+                CameraPlugin,
+                AnimationPlugin,
+                FeedbackPlugin,
+                HighlightPlugin,
+                PreviewPlugin,
+                HintsPlugin,
+                DeadlockPlugin,
+            ));
 
         #[cfg(debug_assertions)]
         {
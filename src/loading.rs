@@ -1,15 +1,47 @@
+use crate::animation::AnimationSet;
+use crate::menu::ButtonColors;
 use crate::GameState;
+use bevy::app::AppExit;
+use bevy::asset::LoadState;
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_kira_audio::AudioSource;
+use iyes_progress::prelude::*;
 
 pub struct LoadingPlugin;
 
+// Everything the game needs is loaded up front while a progress bar is shown.
+// If anything fails to load, `GameState::LoadingFailed` names the missing files
+// instead of the game breaking later on.
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
         app.add_loading_state(
-            LoadingState::new(GameState::Loading).continue_to_state(GameState::Menu),
+            LoadingState::new(GameState::Loading)
+                .on_failure_continue_to_state(GameState::LoadingFailed),
         )
-        .add_collection_to_loading_state::<_, TextureAssets>(GameState::Loading);
+        .add_collection_to_loading_state::<_, TextureAssets>(GameState::Loading)
+        .add_collection_to_loading_state::<_, AudioAssets>(GameState::Loading)
+        .add_collection_to_loading_state::<_, MusicAssets>(GameState::Loading)
+        .add_collection_to_loading_state::<_, AnimationAssets>(GameState::Loading)
+        .add_collection_to_loading_state::<_, LevelAssets>(GameState::Loading)
+        .add_plugins(ProgressPlugin::new(GameState::Loading).continue_to(GameState::Menu))
+        .add_systems(OnEnter(GameState::Loading), setup_loading_screen)
+        .add_systems(
+            Update,
+            update_loading_bar
+                .after(LoadingStateSet(GameState::Loading))
+                .run_if(in_state(GameState::Loading)),
+        )
+        .add_systems(OnExit(GameState::Loading), cleanup_loading_screen)
+        .add_systems(
+            OnEnter(GameState::LoadingFailed),
+            (find_failed_assets, setup_loading_failed).chain(),
+        )
+        .add_systems(
+            Update,
+            quit_after_loading_failed.run_if(in_state(GameState::LoadingFailed)),
+        );
     }
 }
 
@@ -20,3 +52,328 @@ pub struct TextureAssets {
     #[asset(path = "textures/github.png")]
     pub github: Handle<Image>,
 }
+
+#[derive(AssetCollection, Resource)]
+pub struct AudioAssets {
+    #[asset(path = "audio/step.wav")]
+    pub step: Handle<AudioSource>,
+    #[asset(path = "audio/push.wav")]
+    pub push: Handle<AudioSource>,
+    #[asset(path = "audio/pull.wav")]
+    pub pull: Handle<AudioSource>,
+    #[asset(path = "audio/grab.wav")]
+    pub grab: Handle<AudioSource>,
+    #[asset(path = "audio/release.wav")]
+    pub release: Handle<AudioSource>,
+    #[asset(path = "audio/bump.wav")]
+    pub bump: Handle<AudioSource>,
+    #[asset(path = "audio/goal.wav")]
+    pub goal: Handle<AudioSource>,
+    #[asset(path = "audio/level_complete.wav")]
+    pub level_complete: Handle<AudioSource>,
+}
+
+/// Level music is looked up by the path in each level's `music` field, holding
+/// the handles here keeps the tracks loaded so switching levels doesn't stall.
+#[derive(AssetCollection, Resource)]
+pub struct MusicAssets {
+    #[asset(path = "Bemuse.ogg")]
+    pub bemuse: Handle<AudioSource>,
+    #[asset(path = "audio/flying.ogg")]
+    pub flying: Handle<AudioSource>,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct AnimationAssets {
+    #[asset(path = "animations/player.anim.ron")]
+    pub player: Handle<AnimationSet>,
+}
+
+/// The LDtk projects and the tilesets they use. bevy_ecs_ldtk loads the tilesets
+/// on its own, they are listed so the loading screen waits for them as well.
+#[derive(AssetCollection, Resource)]
+pub struct LevelAssets {
+    #[asset(path = "tile-based-game.ldtk")]
+    pub ldtk_project: Handle<LdtkProject>,
+    /// Side-view levels with gravity and ladders.
+    #[asset(path = "Typical_2D_platformer_example.ldtk")]
+    pub platformer_project: Handle<LdtkProject>,
+    /// The hub the levels are entered from.
+    #[asset(path = "WorldMap_Free_layout.ldtk")]
+    pub overworld_map: Handle<LdtkProject>,
+    #[asset(path = "atlas/SunnyLand-player.png")]
+    pub player_tileset: Handle<Image>,
+    #[asset(path = "atlas/SunnyLand_by_Ansimuz-extended.png")]
+    pub level_tileset: Handle<Image>,
+    #[asset(path = "atlas/chibi-layered.png")]
+    pub character_tileset: Handle<Image>,
+    #[asset(path = "atlas/MV Icons Complete Sheet Free - ALL.png")]
+    pub icon_tileset: Handle<Image>,
+    #[asset(path = "atlas/NuclearBlaze_by_deepnight.png")]
+    pub overworld_tileset: Handle<Image>,
+}
+
+impl LevelAssets {
+    /// Every project with playable levels, the top-down puzzles first.
+    pub fn projects(&self) -> [&Handle<LdtkProject>; 2] {
+        [&self.ldtk_project, &self.platformer_project]
+    }
+
+    /// The project holding the selected level. Selections that don't name a
+    /// level by iid refer to the top-down puzzles.
+    pub fn project_for(
+        &self,
+        level_selection: &LevelSelection,
+        ldtk_project_assets: &Assets<LdtkProject>,
+    ) -> Handle<LdtkProject> {
+        let LevelSelection::Iid(level_iid) = level_selection else {
+            return self.ldtk_project.clone();
+        };
+        self.projects()
+            .into_iter()
+            .find(|handle| {
+                ldtk_project_assets
+                    .get(handle)
+                    .and_then(|ldtk_project| ldtk_project.get_raw_level_by_iid(level_iid.get()))
+                    .is_some()
+            })
+            .unwrap_or(&self.ldtk_project)
+            .clone()
+    }
+}
+
+/// Paths of the assets that failed to load, for the loading failed screen.
+#[derive(Resource)]
+struct FailedAssets(Vec<String>);
+
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(Component)]
+struct LoadingBar;
+
+#[derive(Component)]
+struct LoadingText;
+
+#[derive(Component)]
+struct QuitButton;
+
+fn setup_loading_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            LoadingScreen,
+        ))
+        .with_children(|children| {
+            children.spawn((
+                TextBundle::from_section(
+                    "Loading...",
+                    TextStyle {
+                        font_size: 30.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..default()
+                    },
+                ),
+                LoadingText,
+            ));
+            children
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(300.0),
+                        height: Val::Px(20.0),
+                        margin: UiRect::all(Val::Px(10.)),
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(0.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: Color::rgb(0.4, 0.7, 1.0).into(),
+                            ..default()
+                        },
+                        LoadingBar,
+                    ));
+                });
+        });
+}
+
+fn update_loading_bar(
+    progress: Option<Res<ProgressCounter>>,
+    mut bar_query: Query<&mut Style, With<LoadingBar>>,
+    mut text_query: Query<&mut Text, With<LoadingText>>,
+) {
+    let Some(progress) = progress.map(|counter| counter.progress()) else {
+        return;
+    };
+    let fraction = if progress.total == 0 {
+        0.
+    } else {
+        progress.done as f32 / progress.total as f32
+    };
+    for mut style in bar_query.iter_mut() {
+        style.width = Val::Percent(fraction * 100.);
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("Loading... {}%", (fraction * 100.) as u32);
+    }
+}
+
+fn cleanup_loading_screen(mut commands: Commands, query: Query<Entity, With<LoadingScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// loading the collections again hands out the handles the asset server already has,
+// so their load states tell which files failed
+fn find_failed_assets(world: &mut World) {
+    let collections: [fn(&mut World) -> Vec<HandleUntyped>; 5] = [
+        TextureAssets::load,
+        AudioAssets::load,
+        MusicAssets::load,
+        AnimationAssets::load,
+        LevelAssets::load,
+    ];
+    let handles: Vec<HandleUntyped> = collections
+        .into_iter()
+        .flat_map(|load| load(world))
+        .collect();
+    let asset_server = world.resource::<AssetServer>();
+    let failed = handles
+        .iter()
+        .filter(|handle| asset_server.get_load_state(*handle) == LoadState::Failed)
+        .filter_map(|handle| asset_server.get_handle_path(handle))
+        .map(|asset_path| asset_path.path().display().to_string())
+        .collect();
+    world.insert_resource(FailedAssets(failed));
+}
+
+fn setup_loading_failed(mut commands: Commands, failed_assets: Res<FailedAssets>) {
+    for path in &failed_assets.0 {
+        error!("Failed to load asset {path:?}");
+    }
+    let details = if failed_assets.0.is_empty() {
+        "Some of the game's files could not be loaded.".to_string()
+    } else {
+        format!(
+            "These files could not be loaded:\n{}",
+            failed_assets.0.join("\n")
+        )
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|children| {
+            children.spawn(TextBundle::from_section(
+                "Something went wrong",
+                TextStyle {
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+            children.spawn(
+                TextBundle::from_section(
+                    details,
+                    TextStyle {
+                        font_size: 20.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..default()
+                    },
+                )
+                .with_text_alignment(TextAlignment::Center)
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(10.)),
+                    ..default()
+                }),
+            );
+            children.spawn(
+                TextBundle::from_section(
+                    "Please check that the assets folder is complete and start the game again.",
+                    TextStyle {
+                        font_size: 20.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(10.)),
+                    ..default()
+                }),
+            );
+            let button_colors = ButtonColors::default();
+            children
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(140.0),
+                            height: Val::Px(50.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: button_colors.normal.into(),
+                        ..default()
+                    },
+                    button_colors,
+                    QuitButton,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Quit",
+                        TextStyle {
+                            font_size: 30.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                            ..default()
+                        },
+                    ));
+                });
+        });
+}
+
+fn quit_after_loading_failed(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut interaction_query: Query<
+        (&Interaction, &ButtonColors, &mut BackgroundColor),
+        (Changed<Interaction>, With<QuitButton>),
+    >,
+    mut ev_exit: EventWriter<AppExit>,
+) {
+    if keyboard_input.any_just_pressed([KeyCode::Escape, KeyCode::Return]) {
+        ev_exit.send(AppExit);
+    }
+    for (interaction, button_colors, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => ev_exit.send(AppExit),
+            Interaction::Hovered => *color = button_colors.hovered.into(),
+            Interaction::None => *color = button_colors.normal.into(),
+        }
+    }
+}
@@ -5,14 +5,18 @@ use bevy::prelude::*;
 use bevy::window::{PrimaryWindow, WindowResolution};
 use bevy::winit::WinitWindows;
 use bevy::DefaultPlugins;
-use bevy_game::GamePlugin;
+use bevy_game::{GamePlugin, ReplayFile};
 use bevy_kira_audio::Audio;
 use std::io::Cursor;
 use winit::window::Icon;
 
 fn main() {
-    App::new()
-        .init_resource::<Audio>()
+    let mut app = App::new();
+    // `--replay <file>` plays back a recorded attempt instead of waiting for input
+    if let Some(replay_file) = ReplayFile::from_args() {
+        app.insert_resource(replay_file);
+    }
+    app.init_resource::<Audio>()
         .insert_resource(Msaa::Off)
         .insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
         .add_plugins(
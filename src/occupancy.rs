@@ -0,0 +1,304 @@
+use crate::history::undo_turn;
+use crate::pause::LeaveLevel;
+use crate::player::{handle_move_player_event, Movable, Player};
+use crate::tile_map::{cache_wall_locations, Block, EntityWalls, Pullable, Pushable};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use std::collections::HashMap;
+use std::ops::BitOr;
+
+pub struct OccupancyPlugin;
+
+// Every movable is indexed by the world-space cell it stands on, so movement
+// rules look up what is next to them instead of comparing every pair of
+// movables. Only the movables whose grid coords changed are re-indexed.
+impl Plugin for OccupancyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Occupancy>()
+            .add_systems(
+                Update,
+                update_occupancy
+                    .after(handle_move_player_event)
+                    .after(cache_wall_locations)
+                    .after(undo_turn)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(LeaveLevel, clear_occupancy);
+    }
+}
+
+/// What stands on a cell, as far as the movement rules care. These don't
+/// change while an entity is indexed, grabs and moves are looked up instead.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TileFlags(u8);
+
+impl TileFlags {
+    pub const NONE: TileFlags = TileFlags(0);
+    /// Blocks stop enemies, lasers and, unless grabbed, other movables.
+    pub const BLOCK: TileFlags = TileFlags(1);
+    pub const PUSHABLE: TileFlags = TileFlags(1 << 1);
+    pub const PULLABLE: TileFlags = TileFlags(1 << 2);
+    pub const PLAYER: TileFlags = TileFlags(1 << 3);
+
+    pub fn contains(self, flags: TileFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+}
+
+impl BitOr for TileFlags {
+    type Output = TileFlags;
+
+    fn bitor(self, flags: TileFlags) -> TileFlags {
+        TileFlags(self.0 | flags.0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Occupant {
+    pub entity: Entity,
+    pub flags: TileFlags,
+}
+
+impl Occupant {
+    pub fn is_block(&self) -> bool {
+        self.flags.contains(TileFlags::BLOCK)
+    }
+}
+
+/// Which movable stands on which cell, in world-space grid coords.
+#[derive(Default, Resource)]
+pub struct Occupancy {
+    cells: HashMap<GridCoords, Occupant>,
+    positions: HashMap<Entity, GridCoords>,
+    vacated: Vec<GridCoords>,
+}
+
+impl Occupancy {
+    pub fn get(&self, world_coords: GridCoords) -> Option<Occupant> {
+        self.cells.get(&world_coords).copied()
+    }
+
+    pub fn position(&self, entity: Entity) -> Option<GridCoords> {
+        self.positions.get(&entity).copied()
+    }
+
+    /// Cells movables stepped or fell off since the last `take_vacated`.
+    pub fn vacated(&self) -> &[GridCoords] {
+        &self.vacated
+    }
+
+    pub fn take_vacated(&mut self) -> Vec<GridCoords> {
+        std::mem::take(&mut self.vacated)
+    }
+
+    /// Puts the entity on a cell, taking it off the one it was on before.
+    pub fn insert(&mut self, entity: Entity, world_coords: GridCoords, flags: TileFlags) {
+        self.remove(entity);
+        self.cells.insert(world_coords, Occupant { entity, flags });
+        self.positions.insert(entity, world_coords);
+    }
+
+    /// Moves an indexed entity, keeping what kind of occupant it is.
+    pub fn move_to(&mut self, entity: Entity, world_coords: GridCoords) {
+        if let Some(occupant) = self
+            .position(entity)
+            .and_then(|position| self.get(position))
+            .filter(|occupant| occupant.entity == entity)
+        {
+            self.vacated.push(self.positions[&entity]);
+            self.insert(entity, world_coords, occupant.flags);
+        }
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        let Some(position) = self.positions.remove(&entity) else {
+            return;
+        };
+        // another movable may have stepped onto the cell in the same frame
+        if self.get(position).map(|occupant| occupant.entity) == Some(entity) {
+            self.cells.remove(&position);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.positions.clear();
+        self.vacated.clear();
+    }
+}
+
+// a level (re)spawning can move the whole level in world space, so that
+// rebuilds the index, everything else only touches what changed
+pub fn update_occupancy(
+    mut occupancy: ResMut<Occupancy>,
+    mut level_events: EventReader<LevelEvent>,
+    changed_query: Query<
+        (Entity, &GridCoords),
+        (With<Movable>, Or<(Changed<GridCoords>, Added<Movable>)>),
+    >,
+    movable_query: Query<(Entity, &GridCoords), With<Movable>>,
+    flags_query: Query<(
+        Option<&Block>,
+        Option<&Pushable>,
+        Option<&Pullable>,
+        Option<&Player>,
+    )>,
+    mut removed_movables: RemovedComponents<Movable>,
+    walls: EntityWalls,
+) {
+    let tile_flags = |entity: Entity| {
+        let Ok((block, pushable, pullable, player)) = flags_query.get(entity) else {
+            return TileFlags::NONE;
+        };
+        [
+            (block.is_some(), TileFlags::BLOCK),
+            (pushable.is_some(), TileFlags::PUSHABLE),
+            (pullable.is_some(), TileFlags::PULLABLE),
+            (player.is_some(), TileFlags::PLAYER),
+        ]
+        .into_iter()
+        .filter(|(has, _)| *has)
+        .fold(TileFlags::NONE, |flags, (_, flag)| flags | flag)
+    };
+    for entity in removed_movables.iter() {
+        occupancy.remove(entity);
+    }
+    let rebuild = level_events.iter().any(|level_event| {
+        matches!(
+            level_event,
+            LevelEvent::Spawned(_) | LevelEvent::Despawned(_)
+        )
+    });
+    if rebuild {
+        occupancy.clear();
+        for (entity, grid_coords) in movable_query.iter() {
+            let world_coords = walls.of(entity).to_world(*grid_coords);
+            occupancy.insert(entity, world_coords, tile_flags(entity));
+        }
+        return;
+    }
+
+    // take everything off its old cell first, so movables swapping places
+    // don't knock each other out of the index
+    let changed: Vec<_> = changed_query.iter().collect();
+    let previous: Vec<_> = changed
+        .iter()
+        .map(|(entity, ..)| occupancy.position(*entity))
+        .collect();
+    for (entity, ..) in changed.iter() {
+        occupancy.remove(*entity);
+    }
+    for ((entity, grid_coords), previous) in changed.into_iter().zip(previous) {
+        let world_coords = walls.of(entity).to_world(*grid_coords);
+        occupancy.insert(entity, world_coords, tile_flags(entity));
+        if let Some(previous) = previous.filter(|previous| *previous != world_coords) {
+            occupancy.vacated.push(previous);
+        }
+    }
+}
+
+fn clear_occupancy(mut occupancy: ResMut<Occupancy>) {
+    occupancy.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile_map::{LevelWalls, WorldWalls};
+
+    // a level whose first cell sits at (2, 3) in world space, with movables
+    // as children of the level entity the way bevy_ecs_ldtk spawns them
+    fn level_app() -> (App, Entity) {
+        let mut world_walls = WorldWalls::default();
+        world_walls.insert(
+            "level".to_string(),
+            LevelWalls::new(GridCoords::new(2, 3), 8, 8),
+        );
+        let mut app = App::new();
+        app.add_event::<LevelEvent>()
+            .init_resource::<Occupancy>()
+            .insert_resource(world_walls)
+            .add_systems(Update, update_occupancy);
+        let level = app.world.spawn(LevelIid::new("level")).id();
+        (app, level)
+    }
+
+    fn spawn_in(app: &mut App, level: Entity, bundle: impl Bundle) -> Entity {
+        let entity = app.world.spawn(bundle).id();
+        app.world.entity_mut(level).add_child(entity);
+        entity
+    }
+
+    #[test]
+    fn indexes_movables_with_their_flags() {
+        let (mut app, level) = level_app();
+        let block = spawn_in(
+            &mut app,
+            level,
+            (Movable, Block, Pushable, GridCoords::new(1, 1)),
+        );
+        let player = spawn_in(
+            &mut app,
+            level,
+            (Movable, Player::default(), GridCoords::new(2, 1)),
+        );
+        app.update();
+
+        let occupancy = app.world.resource::<Occupancy>();
+        assert_eq!(occupancy.position(block), Some(GridCoords::new(3, 4)));
+        assert_eq!(occupancy.position(player), Some(GridCoords::new(4, 4)));
+        let occupant = occupancy.get(GridCoords::new(3, 4)).unwrap();
+        assert_eq!(occupant.entity, block);
+        assert_eq!(occupant.flags, TileFlags::BLOCK | TileFlags::PUSHABLE);
+        assert!(!occupant.flags.contains(TileFlags::PULLABLE));
+        let occupant = occupancy.get(GridCoords::new(4, 4)).unwrap();
+        assert_eq!(occupant.entity, player);
+        assert_eq!(occupant.flags, TileFlags::PLAYER);
+    }
+
+    #[test]
+    fn a_move_frees_the_old_cell() {
+        let (mut app, level) = level_app();
+        let block = spawn_in(
+            &mut app,
+            level,
+            (Movable, Block, Pushable, GridCoords::new(1, 1)),
+        );
+        app.update();
+        app.world.resource_mut::<Occupancy>().take_vacated();
+
+        *app.world.get_mut::<GridCoords>(block).unwrap() = GridCoords::new(1, 2);
+        app.update();
+
+        let occupancy = app.world.resource::<Occupancy>();
+        assert_eq!(occupancy.get(GridCoords::new(3, 4)), None);
+        assert_eq!(occupancy.position(block), Some(GridCoords::new(3, 5)));
+        assert_eq!(
+            occupancy
+                .get(GridCoords::new(3, 5))
+                .map(|occupant| occupant.flags),
+            Some(TileFlags::BLOCK | TileFlags::PUSHABLE)
+        );
+        assert_eq!(occupancy.vacated(), &[GridCoords::new(3, 4)]);
+    }
+
+    #[test]
+    fn a_despawn_removes_the_entry() {
+        let (mut app, level) = level_app();
+        let block = spawn_in(
+            &mut app,
+            level,
+            (Movable, Block, Pushable, GridCoords::new(1, 1)),
+        );
+        app.update();
+        assert!(app.world.resource::<Occupancy>().position(block).is_some());
+
+        app.world.despawn(block);
+        app.update();
+
+        let occupancy = app.world.resource::<Occupancy>();
+        assert_eq!(occupancy.get(GridCoords::new(3, 4)), None);
+        assert_eq!(occupancy.position(block), None);
+    }
+}
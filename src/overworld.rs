@@ -0,0 +1,549 @@
+use crate::camera::{CameraTarget, MainCamera};
+use crate::loading::LevelAssets;
+use crate::player::{get_movement_coords_from_direction, Direction};
+use crate::progress::{is_level_unlocked, is_playable};
+use crate::save::SaveData;
+use crate::tile_map::GRID_SIZE;
+use crate::GameState;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_ecs_ldtk::utils::grid_coords_to_translation;
+use bevy_tweening::{lens::TransformPositionLens, Animator, EaseFunction, Tween};
+use std::time::Duration;
+
+pub struct OverworldPlugin;
+
+// The hub is the WorldMap LDtk project, walked cell by cell. Only the room the
+// walker stands in is spawned, stepping over its edge selects the next room.
+// `LevelEntrance` entities on the map name the level they lead into.
+impl Plugin for OverworldPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Overworld>()
+            .add_systems(OnEnter(GameState::Overworld), setup_overworld)
+            .add_systems(
+                Update,
+                (
+                    build_overworld,
+                    walk_on_overworld.after(build_overworld),
+                    enter_level_from_overworld.after(walk_on_overworld),
+                    follow_walker.after(walk_on_overworld),
+                    update_overworld_entrances.after(build_overworld),
+                )
+                    .run_if(in_state(GameState::Overworld)),
+            )
+            .add_systems(OnExit(GameState::Overworld), cleanup_overworld);
+    }
+}
+
+const STEP_DURATION: Duration = Duration::from_millis(150);
+const STICK_DEADZONE: f32 = 0.5;
+const ENTRANCE_SIZE: f32 = 12.;
+const COMPLETED_COLOR: Color = Color::rgb(0.3, 0.8, 0.3);
+const UNLOCKED_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const LOCKED_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+
+/// A room of the hub and which of its cells can be walked on.
+pub struct OverworldRoom {
+    pub level_iid: String,
+    /// World grid coords of the bottom left cell.
+    pub origin: GridCoords,
+    pub width: i32,
+    pub height: i32,
+    walkable: Vec<bool>,
+}
+
+impl OverworldRoom {
+    fn index(&self, world_coords: GridCoords) -> Option<usize> {
+        let x = world_coords.x - self.origin.x;
+        let y = world_coords.y - self.origin.y;
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some((y * self.width + x) as usize)
+    }
+
+    fn is_walkable(&self, world_coords: GridCoords) -> bool {
+        self.index(world_coords)
+            .is_some_and(|index| self.walkable[index])
+    }
+
+    fn bounds(&self) -> Rect {
+        let min = Vec2::new(self.origin.x as f32, self.origin.y as f32) * GRID_SIZE as f32;
+        Rect::from_corners(
+            min,
+            min + Vec2::new(self.width as f32, self.height as f32) * GRID_SIZE as f32,
+        )
+    }
+}
+
+/// A door on the hub leading into a level.
+pub struct OverworldEntrance {
+    pub level_iid: String,
+    pub identifier: String,
+    pub grid_coords: GridCoords,
+    /// The first level of every project is open from the start.
+    pub is_first: bool,
+    pub neighbour_iids: Vec<String>,
+}
+
+impl OverworldEntrance {
+    pub fn is_unlocked(&self, save_data: &SaveData) -> bool {
+        is_level_unlocked(
+            self.is_first,
+            &self.level_iid,
+            self.neighbour_iids.iter().map(String::as_str),
+            save_data,
+        )
+    }
+}
+
+#[derive(Default, Resource)]
+pub struct Overworld {
+    pub rooms: Vec<OverworldRoom>,
+    pub entrances: Vec<OverworldEntrance>,
+    /// Where the walker stands, kept while a level is played.
+    pub position: Option<GridCoords>,
+}
+
+impl Overworld {
+    fn room_at(&self, world_coords: GridCoords) -> Option<&OverworldRoom> {
+        self.rooms
+            .iter()
+            .find(|room| room.index(world_coords).is_some())
+    }
+
+    fn is_walkable(&self, world_coords: GridCoords) -> bool {
+        self.room_at(world_coords)
+            .is_some_and(|room| room.is_walkable(world_coords))
+    }
+
+    fn entrance_at(&self, world_coords: GridCoords) -> Option<&OverworldEntrance> {
+        self.entrances
+            .iter()
+            .find(|entrance| entrance.grid_coords == world_coords)
+    }
+}
+
+#[derive(Component)]
+struct OverworldEntity;
+
+#[derive(Component)]
+struct OverworldEntranceSprite(usize);
+
+#[derive(Component)]
+struct OverworldWalker {
+    step: Timer,
+}
+
+/// Keyboard, gamepad, mouse and touch input for walking the hub.
+#[derive(SystemParam)]
+struct OverworldInput<'w, 's> {
+    keyboard_input: Res<'w, Input<KeyCode>>,
+    gamepads: Res<'w, Gamepads>,
+    gamepad_input: Res<'w, Input<GamepadButton>>,
+    gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+    mouse_input: Res<'w, Input<MouseButton>>,
+    touch_input: Res<'w, Touches>,
+    window_query: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    camera_query: Query<'w, 's, (&'static Camera, &'static GlobalTransform), With<MainCamera>>,
+}
+
+impl<'w, 's> OverworldInput<'w, 's> {
+    fn gamepad_pressed(&self, button_type: GamepadButtonType) -> bool {
+        self.gamepads.iter().any(|gamepad| {
+            self.gamepad_input
+                .pressed(GamepadButton::new(gamepad, button_type))
+        })
+    }
+
+    /// Where a finger or the left mouse button is held down, in world space.
+    fn pointer(&self) -> Option<Vec2> {
+        let (camera, camera_transform) = self.camera_query.get_single().ok()?;
+        let viewport_position = self.touch_input.first_pressed_position().or_else(|| {
+            if !self.mouse_input.pressed(MouseButton::Left) {
+                return None;
+            }
+            self.window_query.get_single().ok()?.cursor_position()
+        })?;
+        camera.viewport_to_world_2d(camera_transform, viewport_position)
+    }
+
+    /// Held keys and the d-pad win over the stick, which wins over steering
+    /// towards the pointer.
+    fn direction(&self, walker_position: Vec2) -> Direction {
+        let held = |key_codes: [KeyCode; 2], button_type| {
+            self.keyboard_input.any_pressed(key_codes) || self.gamepad_pressed(button_type)
+        };
+        if held([KeyCode::W, KeyCode::Up], GamepadButtonType::DPadUp) {
+            return Direction::North;
+        } else if held([KeyCode::A, KeyCode::Left], GamepadButtonType::DPadLeft) {
+            return Direction::West;
+        } else if held([KeyCode::S, KeyCode::Down], GamepadButtonType::DPadDown) {
+            return Direction::South;
+        } else if held([KeyCode::D, KeyCode::Right], GamepadButtonType::DPadRight) {
+            return Direction::East;
+        }
+
+        let stick = self
+            .gamepads
+            .iter()
+            .map(|gamepad| {
+                let axis = |axis_type| {
+                    self.gamepad_axes
+                        .get(GamepadAxis::new(gamepad, axis_type))
+                        .unwrap_or(0.)
+                };
+                Vec2::new(
+                    axis(GamepadAxisType::LeftStickX),
+                    axis(GamepadAxisType::LeftStickY),
+                )
+            })
+            .find(|stick| stick.length() > STICK_DEADZONE);
+        let steering = stick.or_else(|| {
+            let offset = self.pointer()? - walker_position;
+            (offset.abs().max_element() > GRID_SIZE as f32 / 2.).then_some(offset)
+        });
+        match steering {
+            Some(steering) if steering.x.abs() > steering.y.abs() && steering.x > 0. => {
+                Direction::East
+            }
+            Some(steering) if steering.x.abs() > steering.y.abs() => Direction::West,
+            Some(steering) if steering.y > 0. => Direction::North,
+            Some(_) => Direction::South,
+            None => Direction::None,
+        }
+    }
+
+    /// Confirming also works by tapping or clicking the walker.
+    fn confirmed(&self, walker_position: Vec2) -> bool {
+        let gamepad_confirmed = self.gamepads.iter().any(|gamepad| {
+            self.gamepad_input
+                .just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South))
+        });
+        let pointer_confirmed = (self.mouse_input.just_pressed(MouseButton::Left)
+            || self.touch_input.iter_just_pressed().next().is_some())
+            && self.pointer().is_some_and(|pointer| {
+                (pointer - walker_position).abs().max_element() <= GRID_SIZE as f32 / 2.
+            });
+        self.keyboard_input
+            .any_just_pressed([KeyCode::Space, KeyCode::Return])
+            || gamepad_confirmed
+            || pointer_confirmed
+    }
+}
+
+fn cell_translation(world_coords: GridCoords, z: f32) -> Vec3 {
+    grid_coords_to_translation(world_coords, IVec2::splat(GRID_SIZE)).extend(z)
+}
+
+fn read_rooms(map: &LdtkProject) -> Vec<OverworldRoom> {
+    map.iter_raw_levels()
+        // other depths of the map overlap the ground floor
+        .filter(|level| level.world_depth == 0)
+        .filter_map(|level| {
+            let collisions = level
+                .layer_instances
+                .iter()
+                .flatten()
+                .find(|layer_instance| layer_instance.identifier == "Collisions")?;
+            // the csv starts at the top row, grid coords at the bottom one
+            let walkable = (0..collisions.c_hei)
+                .rev()
+                .flat_map(|row| {
+                    (0..collisions.c_wid).map(move |column| {
+                        collisions.int_grid_csv[(row * collisions.c_wid + column) as usize] == 0
+                    })
+                })
+                .collect();
+            Some(OverworldRoom {
+                level_iid: level.iid.clone(),
+                origin: GridCoords::new(
+                    level.world_x / GRID_SIZE,
+                    -(level.world_y + level.px_hei) / GRID_SIZE,
+                ),
+                width: collisions.c_wid,
+                height: collisions.c_hei,
+                walkable,
+            })
+        })
+        .collect()
+}
+
+fn read_entrances(map: &LdtkProject, projects: &[&LdtkProject]) -> Vec<OverworldEntrance> {
+    let mut entrances = Vec::new();
+    for level in map.iter_raw_levels().filter(|level| level.world_depth == 0) {
+        let entity_instances = level
+            .layer_instances
+            .iter()
+            .flatten()
+            .flat_map(|layer_instance| layer_instance.entity_instances.iter())
+            .filter(|entity_instance| entity_instance.identifier == "LevelEntrance");
+        for entity_instance in entity_instances {
+            let Ok(level_iid) = entity_instance.get_string_field("level") else {
+                continue;
+            };
+            let Some((index, target)) = projects.iter().find_map(|ldtk_project| {
+                ldtk_project
+                    .iter_raw_levels()
+                    .filter(|level| is_playable(level))
+                    .enumerate()
+                    .find(|(_, level)| level.iid == *level_iid)
+            }) else {
+                warn!("Hub entrance leads to unknown level {level_iid}");
+                continue;
+            };
+            entrances.push(OverworldEntrance {
+                level_iid: level_iid.clone(),
+                identifier: target.identifier.clone(),
+                // LDtk's y points down, the rows of the room are counted from its top
+                grid_coords: GridCoords::new(
+                    level.world_x / GRID_SIZE + entity_instance.grid.x,
+                    -level.world_y / GRID_SIZE - 1 - entity_instance.grid.y,
+                ),
+                is_first: index == 0,
+                neighbour_iids: target
+                    .neighbours
+                    .iter()
+                    .map(|neighbour| neighbour.level_iid.clone())
+                    .collect(),
+            });
+        }
+    }
+    entrances
+}
+
+fn setup_overworld(mut commands: Commands, level_assets: Res<LevelAssets>) {
+    commands.spawn((
+        LdtkWorldBundle {
+            ldtk_handle: level_assets.overworld_map.clone(),
+            ..Default::default()
+        },
+        OverworldEntity,
+    ));
+}
+
+// the map is read once, the walker and entrances are spawned on every visit
+fn build_overworld(
+    mut commands: Commands,
+    mut overworld: ResMut<Overworld>,
+    level_assets: Res<LevelAssets>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    walker_query: Query<(), With<OverworldWalker>>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut camera_target: ResMut<CameraTarget>,
+) {
+    if !walker_query.is_empty() {
+        return;
+    }
+    if overworld.rooms.is_empty() {
+        let Some(map) = ldtk_project_assets.get(&level_assets.overworld_map) else {
+            return;
+        };
+        let Some(projects) = level_assets
+            .projects()
+            .into_iter()
+            .map(|handle| ldtk_project_assets.get(handle))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+        overworld.rooms = read_rooms(map);
+        overworld.entrances = read_entrances(map, &projects);
+    }
+    // the walker starts out at the first level
+    let Some(position) = overworld.position.or_else(|| {
+        overworld
+            .entrances
+            .iter()
+            .find(|entrance| entrance.is_first)
+            .map(|entrance| entrance.grid_coords)
+    }) else {
+        warn!("The hub has no entrance to start from");
+        return;
+    };
+    let Some(room) = overworld.room_at(position) else {
+        return;
+    };
+    *level_selection = LevelSelection::iid(room.level_iid.clone());
+    camera_target.frame(room.bounds());
+
+    for (index, entrance) in overworld.entrances.iter().enumerate() {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: LOCKED_COLOR,
+                    custom_size: Some(Vec2::splat(ENTRANCE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(cell_translation(entrance.grid_coords, 10.)),
+                ..default()
+            },
+            OverworldEntranceSprite(index),
+            OverworldEntity,
+        ));
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    entrance.identifier.clone(),
+                    TextStyle {
+                        font_size: 32.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..default()
+                    },
+                ),
+                transform: Transform::from_translation(
+                    cell_translation(entrance.grid_coords, 10.)
+                        + Vec3::new(0., GRID_SIZE as f32, 0.),
+                )
+                .with_scale(Vec3::splat(0.25)),
+                ..default()
+            },
+            OverworldEntity,
+        ));
+    }
+
+    let texture_atlas = TextureAtlas::from_grid(
+        level_assets.character_tileset.clone(),
+        Vec2::splat(GRID_SIZE as f32),
+        9,
+        3,
+        None,
+        None,
+    );
+    commands.spawn((
+        SpriteSheetBundle {
+            texture_atlas: texture_atlases.add(texture_atlas),
+            transform: Transform::from_translation(cell_translation(position, 11.)),
+            ..default()
+        },
+        OverworldWalker {
+            step: Timer::new(STEP_DURATION, TimerMode::Once),
+        },
+        OverworldEntity,
+    ));
+    overworld.position = Some(position);
+}
+
+// holding a direction keeps walking, one cell per step
+fn walk_on_overworld(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut overworld: ResMut<Overworld>,
+    save_data: Res<SaveData>,
+    input: OverworldInput,
+    mut walker_query: Query<(Entity, &mut OverworldWalker, &Transform)>,
+    mut level_selection: ResMut<LevelSelection>,
+) {
+    let Some(position) = overworld.position else {
+        return;
+    };
+    for (entity, mut walker, transform) in walker_query.iter_mut() {
+        walker.step.tick(time.delta());
+        if !walker.step.finished() {
+            continue;
+        }
+        let direction = input.direction(transform.translation.truncate());
+        let Some(step) = get_movement_coords_from_direction(direction) else {
+            continue;
+        };
+        let target = position + step;
+        if !overworld.is_walkable(target) {
+            continue;
+        }
+        walker.step.reset();
+        overworld.position = Some(target);
+
+        let tween = Tween::new(
+            save_data.settings.ease(EaseFunction::QuadraticInOut),
+            STEP_DURATION,
+            TransformPositionLens {
+                start: transform.translation,
+                end: cell_translation(target, 11.),
+            },
+        );
+        commands.entity(entity).insert(Animator::new(tween));
+
+        // stepping over the edge of the room spawns the next one
+        if let Some(room) = overworld
+            .room_at(target)
+            .filter(|room| room.index(position).is_none())
+        {
+            *level_selection = LevelSelection::iid(room.level_iid.clone());
+        }
+    }
+}
+
+fn enter_level_from_overworld(
+    overworld: Res<Overworld>,
+    save_data: Res<SaveData>,
+    input: OverworldInput,
+    walker_query: Query<&Transform, With<OverworldWalker>>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Ok(transform) = walker_query.get_single() else {
+        return;
+    };
+    if !input.confirmed(transform.translation.truncate()) {
+        return;
+    }
+    let Some(entrance) = overworld
+        .position
+        .and_then(|position| overworld.entrance_at(position))
+    else {
+        return;
+    };
+    if !entrance.is_unlocked(&save_data) {
+        return;
+    }
+    *level_selection = LevelSelection::iid(entrance.level_iid.clone());
+    next_state.set(GameState::Playing);
+}
+
+// rooms larger than the screen scroll along with the walker
+fn follow_walker(
+    overworld: Res<Overworld>,
+    walker_query: Query<&Transform, With<OverworldWalker>>,
+    mut camera_target: ResMut<CameraTarget>,
+) {
+    let Some(room) = overworld
+        .position
+        .and_then(|position| overworld.room_at(position))
+    else {
+        return;
+    };
+    let Ok(transform) = walker_query.get_single() else {
+        return;
+    };
+    camera_target.bounds = room.bounds();
+    camera_target.focus = Some(transform.translation.truncate());
+}
+
+fn update_overworld_entrances(
+    overworld: Res<Overworld>,
+    save_data: Res<SaveData>,
+    mut entrance_query: Query<(&OverworldEntranceSprite, &mut Sprite)>,
+) {
+    for (entrance, mut sprite) in entrance_query.iter_mut() {
+        let entrance = &overworld.entrances[entrance.0];
+        let color = if save_data.is_completed(&entrance.level_iid) {
+            COMPLETED_COLOR
+        } else if entrance.is_unlocked(&save_data) {
+            UNLOCKED_COLOR
+        } else {
+            LOCKED_COLOR
+        };
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
+
+fn cleanup_overworld(mut commands: Commands, query: Query<Entity, With<OverworldEntity>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,322 @@
+use crate::history::RestartEvent;
+use crate::menu::ButtonColors;
+use crate::settings::SettingsReturn;
+use crate::GameState;
+use bevy::ecs::schedule::ScheduleLabel;
+use bevy::prelude::*;
+use bevy::window::WindowFocused;
+
+pub struct PausePlugin;
+
+// Pausing leaves `GameState::Playing`, which stops every gameplay system, while the
+// level itself stays spawned, also while the settings screen is opened from the
+// pause menu. Level setup checks `resuming` and teardown lives in the `LeaveLevel`
+// schedule, so they only run when a level is actually entered or left.
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Resuming>()
+            .init_resource::<PauseFocus>()
+            .add_systems(
+                OnExit(GameState::Playing),
+                run_leave_level.run_if(leaving_level),
+            )
+            .add_systems(
+                OnExit(GameState::Paused),
+                run_leave_level.run_if(leaving_level),
+            )
+            .add_systems(OnExit(GameState::LevelComplete), run_leave_level)
+            .add_systems(OnEnter(GameState::Paused), (freeze_time, setup_pause_menu))
+            .add_systems(
+                OnExit(GameState::Paused),
+                (
+                    unfreeze_time.run_if(not(in_state(GameState::Settings))),
+                    cleanup_pause_menu,
+                    mark_resuming,
+                ),
+            )
+            .add_systems(
+                Update,
+                (pause_from_input, finish_resuming.run_if(resuming))
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Update,
+                (
+                    navigate_pause_menu,
+                    click_pause_button,
+                    update_pause_button_colors
+                        .after(navigate_pause_menu)
+                        .after(click_pause_button),
+                )
+                    .run_if(in_state(GameState::Paused)),
+            );
+    }
+}
+
+/// Set while coming back from `GameState::Paused` into the level that was paused.
+#[derive(Default, Resource)]
+pub struct Resuming(bool);
+
+pub fn resuming(resuming: Res<Resuming>) -> bool {
+    resuming.0
+}
+
+/// True on `OnExit` of `Playing` or `Paused` unless the game just moves between the two,
+/// opens the settings from the pause menu or shows the results of the level.
+pub fn leaving_level(state: Res<State<GameState>>) -> bool {
+    !matches!(
+        state.get(),
+        GameState::Playing | GameState::Paused | GameState::Settings | GameState::LevelComplete
+    )
+}
+
+/// Runs once whenever the game leaves a level, whichever of its states it was in.
+/// The results screen always leaves, retrying spawns the level afresh.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LeaveLevel;
+
+fn run_leave_level(world: &mut World) {
+    // the schedule only exists once some plugin has added teardown to it
+    let _ = world.try_run_schedule(LeaveLevel);
+}
+
+#[derive(Default, Resource)]
+struct PauseFocus(usize);
+
+#[derive(Component)]
+struct PauseMenu;
+
+#[derive(Clone, Copy, PartialEq)]
+enum PauseAction {
+    Resume,
+    Restart,
+    LevelSelect,
+    Settings,
+    QuitToMenu,
+}
+
+const PAUSE_ACTIONS: [(PauseAction, &str); 5] = [
+    (PauseAction::Resume, "Resume"),
+    (PauseAction::Restart, "Restart"),
+    (PauseAction::LevelSelect, "Level Select"),
+    (PauseAction::Settings, "Settings"),
+    (PauseAction::QuitToMenu, "Quit to Menu"),
+];
+
+#[derive(Component)]
+struct PauseButton {
+    index: usize,
+    action: PauseAction,
+}
+
+fn gamepad_just_pressed(
+    gamepads: &Gamepads,
+    gamepad_input: &Input<GamepadButton>,
+    button_type: GamepadButtonType,
+) -> bool {
+    gamepads
+        .iter()
+        .any(|gamepad| gamepad_input.just_pressed(GamepadButton::new(gamepad, button_type)))
+}
+
+// losing focus also covers the app being suspended on mobile and hidden tabs on the web
+fn pause_from_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut ev_window_focused: EventReader<WindowFocused>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let focus_lost = ev_window_focused.iter().any(|event| !event.focused);
+    if focus_lost
+        || keyboard_input.just_pressed(KeyCode::Escape)
+        || gamepad_just_pressed(&gamepads, &gamepad_input, GamepadButtonType::Start)
+    {
+        next_state.set(GameState::Paused);
+    }
+}
+
+fn finish_resuming(mut resuming: ResMut<Resuming>) {
+    resuming.0 = false;
+}
+
+fn mark_resuming(state: Res<State<GameState>>, mut resuming: ResMut<Resuming>) {
+    resuming.0 = *state.get() == GameState::Playing;
+}
+
+// tweens, death timers and the level clock all run on virtual time
+fn freeze_time(mut time: ResMut<Time>) {
+    time.pause();
+}
+
+fn unfreeze_time(mut time: ResMut<Time>) {
+    time.unpause();
+}
+
+fn setup_pause_menu(mut commands: Commands, mut focus: ResMut<PauseFocus>) {
+    focus.0 = 0;
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            PauseMenu,
+        ))
+        .with_children(|children| {
+            children.spawn(TextBundle::from_section(
+                "Paused",
+                TextStyle {
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+            for (index, (action, label)) in PAUSE_ACTIONS.into_iter().enumerate() {
+                let button_colors = ButtonColors::default();
+                children
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(220.0),
+                                height: Val::Px(50.0),
+                                margin: UiRect::all(Val::Px(5.)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: button_colors.normal.into(),
+                            ..default()
+                        },
+                        button_colors,
+                        PauseButton { index, action },
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            label,
+                            TextStyle {
+                                font_size: 30.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+fn apply_pause_action(
+    action: PauseAction,
+    next_state: &mut NextState<GameState>,
+    ev_restart: &mut EventWriter<RestartEvent>,
+    settings_return: &mut SettingsReturn,
+) {
+    match action {
+        PauseAction::Resume => next_state.set(GameState::Playing),
+        PauseAction::Restart => {
+            // read by the history and the replay recorder once the level is running again
+            ev_restart.send(RestartEvent);
+            next_state.set(GameState::Playing);
+        }
+        PauseAction::LevelSelect => next_state.set(GameState::LevelSelect),
+        PauseAction::Settings => {
+            settings_return.0 = GameState::Paused;
+            next_state.set(GameState::Settings);
+        }
+        PauseAction::QuitToMenu => next_state.set(GameState::Menu),
+    }
+}
+
+fn navigate_pause_menu(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut focus: ResMut<PauseFocus>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut ev_restart: EventWriter<RestartEvent>,
+    mut settings_return: ResMut<SettingsReturn>,
+) {
+    let gamepad_pressed =
+        |button_type| gamepad_just_pressed(&gamepads, &gamepad_input, button_type);
+
+    if keyboard_input.any_just_pressed([KeyCode::W, KeyCode::Up])
+        || gamepad_pressed(GamepadButtonType::DPadUp)
+    {
+        focus.0 = focus.0.saturating_sub(1);
+    } else if keyboard_input.any_just_pressed([KeyCode::S, KeyCode::Down])
+        || gamepad_pressed(GamepadButtonType::DPadDown)
+    {
+        focus.0 = (focus.0 + 1).min(PAUSE_ACTIONS.len() - 1);
+    } else if keyboard_input.any_just_pressed([KeyCode::Return, KeyCode::Space])
+        || gamepad_pressed(GamepadButtonType::South)
+    {
+        apply_pause_action(
+            PAUSE_ACTIONS[focus.0].0,
+            &mut next_state,
+            &mut ev_restart,
+            &mut settings_return,
+        );
+    } else if keyboard_input.just_pressed(KeyCode::Escape)
+        || gamepad_pressed(GamepadButtonType::Start)
+        || gamepad_pressed(GamepadButtonType::East)
+    {
+        next_state.set(GameState::Playing);
+    }
+}
+
+// mouse and touch both go through ui interactions
+fn click_pause_button(
+    interaction_query: Query<(&Interaction, &PauseButton), Changed<Interaction>>,
+    mut focus: ResMut<PauseFocus>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut ev_restart: EventWriter<RestartEvent>,
+    mut settings_return: ResMut<SettingsReturn>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        match *interaction {
+            Interaction::Pressed => {
+                focus.0 = button.index;
+                apply_pause_action(
+                    button.action,
+                    &mut next_state,
+                    &mut ev_restart,
+                    &mut settings_return,
+                );
+            }
+            Interaction::Hovered => focus.0 = button.index,
+            Interaction::None => {}
+        }
+    }
+}
+
+fn update_pause_button_colors(
+    focus: Res<PauseFocus>,
+    mut button_query: Query<(&PauseButton, &ButtonColors, &mut BackgroundColor)>,
+) {
+    for (button, button_colors, mut color) in button_query.iter_mut() {
+        let new_color = if button.index == focus.0 {
+            button_colors.hovered
+        } else {
+            button_colors.normal
+        };
+        if color.0 != new_color {
+            color.0 = new_color;
+        }
+    }
+}
+
+fn cleanup_pause_menu(mut commands: Commands, query: Query<Entity, With<PauseMenu>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,15 +1,22 @@
+use crate::actions::Actions;
+use crate::error::GameError;
+use crate::gravity::LevelRules;
+use crate::hazards::player_alive;
+use crate::occupancy::Occupancy;
 use crate::tile_map::Block;
-use crate::tile_map::Goal;
+use crate::tile_map::EntityWalls;
 use crate::tile_map::IsMoving;
-use crate::tile_map::LevelWalls;
 use crate::tile_map::Pushable;
+use crate::tile_map::Tile;
 use crate::GameState;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use bevy_ecs_ldtk::{GridCoords, LdtkEntity, LevelSelection};
+use bevy_ecs_ldtk::{GridCoords, LdtkEntity};
+use serde::{Deserialize, Serialize};
 
 pub struct PlayerPlugin;
 
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum Direction {
     North,
     #[default]
@@ -37,37 +44,16 @@ pub struct Player {
     face_direction: Direction,
 }
 
-#[derive(Default, Component)]
-pub struct Movable {
-    north_neighbor: Option<Entity>,
-    east_neighbor: Option<Entity>,
-    south_neighbor: Option<Entity>,
-    west_neighbor: Option<Entity>,
-}
-
-pub fn update_moveable_neighbors(
-    mut movable_query: Query<(&GridCoords, &mut Movable)>,
-    neighbor_query: Query<(Entity, &GridCoords), With<Movable>>,
-) {
-    for (movable_grid_coords, mut movable) in movable_query.iter_mut() {
-        movable.north_neighbor = None;
-        movable.east_neighbor = None;
-        movable.south_neighbor = None;
-        movable.west_neighbor = None;
-        for (entity, neighbor_coords) in neighbor_query.iter() {
-            if *movable_grid_coords + GridCoords::new(0, 1) == *neighbor_coords {
-                movable.north_neighbor = Some(entity);
-            } else if *movable_grid_coords + GridCoords::new(1, 0) == *neighbor_coords {
-                movable.east_neighbor = Some(entity);
-            } else if *movable_grid_coords + GridCoords::new(0, -1) == *neighbor_coords {
-                movable.south_neighbor = Some(entity);
-            } else if *movable_grid_coords + GridCoords::new(-1, 0) == *neighbor_coords {
-                movable.west_neighbor = Some(entity);
-            }
-        }
+impl Player {
+    pub fn face_direction(&self) -> Direction {
+        self.face_direction
     }
 }
 
+/// Players and blocks, everything the `Occupancy` index keeps track of.
+#[derive(Default, Component)]
+pub struct Movable;
+
 #[derive(Default, Bundle, LdtkEntity)]
 pub struct PlayerBundle {
     player: Player,
@@ -84,114 +70,241 @@ impl Plugin for PlayerPlugin {
         app.add_event::<PushMoveEvent>();
         app.add_event::<PlayerMoveEvent>();
         app.add_event::<PullMoveEvent>();
+        app.add_event::<TurnEvent>();
+        app.add_event::<MoveBlocked>();
         app.add_systems(
             Update,
             (
-                turn_player_from_input.run_if(in_state(GameState::Playing)),
-                grab_from_held_input.run_if(in_state(GameState::Playing)),
+                turn_player_from_input
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(player_alive),
+                grab_from_held_input
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(player_alive),
                 ungrab_from_release_input.run_if(in_state(GameState::Playing)),
-                highlight_grabbed.run_if(in_state(GameState::Playing)),
-                unhighlight_grabbed.run_if(in_state(GameState::Playing)),
-                update_player_facing_direction.run_if(in_state(GameState::Playing)),
-                // check_goal.run_if(in_state(GameState::Playing)),
                 // move_pushable_from_input.run_if(in_state(GameState::Playing)),
                 //
                 handle_move_player
                     // .before(translate_grid_coords_entities)
-                    .run_if(in_state(GameState::Playing)),
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(player_alive),
                 handle_move_player_event
                     .run_if(in_state(GameState::Playing))
                     .after(handle_move_player),
-                update_moveable_neighbors
-                    .after(handle_move_player_event)
-                    .run_if(in_state(GameState::Playing)),
             ),
         );
     }
 }
 #[derive(Event)]
-pub struct PushMoveEvent(Entity, Direction);
+pub struct PushMoveEvent(pub Entity, pub Direction);
 
 #[derive(Event)]
-pub struct PullMoveEvent(Entity, Direction);
+pub struct PullMoveEvent(pub Entity, pub Direction);
 
 #[derive(Event)]
-pub struct PlayerMoveEvent(Entity, Direction);
+pub struct PlayerMoveEvent(pub Entity, pub Direction);
 
-pub fn handle_move_player(
-    mut commands: Commands,
-    moving_player_query: Query<Entity, (With<Player>, With<IsMoving>)>,
-    mut player_query: Query<
+/// Sent once for every turn in which at least one player starts moving.
+#[derive(Event)]
+pub struct TurnEvent(pub Direction);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlockedReason {
+    Wall,
+    UnpushableBlock,
+    /// More movables in a row than the level's `push_limit` allows.
+    ChainTooLong,
+    /// Grabbing only allows moving along the grab direction.
+    GrabDirectionMismatch,
+    /// With gravity, going up needs a ladder.
+    NoLadder,
+}
+
+/// Sent for every player or grabbed block that was asked to move but couldn't.
+#[derive(Event)]
+pub struct MoveBlocked {
+    pub entity: Entity,
+    pub direction: Direction,
+    pub reason: BlockedReason,
+}
+
+/// The rules deciding whether a player or grabbed block can take a step.
+/// Shared by the real moves and anything that wants to predict them.
+#[derive(SystemParam)]
+pub struct MoveRules<'w, 's> {
+    mover_query: Query<
+        'w,
+        's,
         (
-            Entity,
-            &Movable,
-            Option<&Block>,
-            Option<&Grabbed>,
-            Option<&Grabbing>,
+            Option<&'static Block>,
+            Option<&'static Grabbed>,
+            Option<&'static Grabbing>,
         ),
-        Without<IsMoving>,
+        With<Movable>,
     >,
-    grid_coords_query: Query<&GridCoords, With<Movable>>,
-    movible_query: Query<&Movable>,
-    block_query: Query<&Block, Without<Grabbed>>,
-    level_walls: Res<LevelWalls>,
-    input: Res<Input<KeyCode>>,
-    mut ev_player_move: EventWriter<PlayerMoveEvent>,
-    global_player_state: Res<GlobalPlayerState>,
-) {
-    // if any player is moving, don't move any players
-    // this is very important because otherwise the will move
-    // out of sync and have a chance of merging into one space
-    if moving_player_query.iter().count() > 0 {
-        return;
-    }
-    let movement_direction = get_movement_direction_from_input(&input);
-    if movement_direction == Direction::None {
-        return;
+    grid_coords_query: Query<'w, 's, &'static GridCoords, With<Movable>>,
+    occupancy: Res<'w, Occupancy>,
+    walls: EntityWalls<'w, 's>,
+    global_player_state: Res<'w, GlobalPlayerState>,
+    level_rules: Res<'w, LevelRules>,
+}
+
+impl<'w, 's> MoveRules<'w, 's> {
+    /// Players and grabbed blocks try to move with input, other blocks only
+    /// get in the way.
+    pub fn is_mover(&self, entity: Entity) -> bool {
+        self.mover_query
+            .get(entity)
+            .map_or(false, |(block, grabbed, _)| {
+                block.is_none() || grabbed.is_some()
+            })
     }
-    for (entity, movable, block, grabbed, grabbing) in player_query.iter_mut() {
-        if grabbed.is_none() && block.is_some() {
-            continue;
-        }
+
+    pub fn check(&self, entity: Entity, direction: Direction) -> Result<(), BlockedReason> {
+        let Ok((block, grabbed, grabbing)) = self.mover_query.get(entity) else {
+            return Err(BlockedReason::Wall);
+        };
 
         // if grabbing, only move if facing direction is the same as movement direction
-        if (grabbing.is_some() || grabbed.is_some() || global_player_state.grabbing)
-            && (global_player_state.direction != movement_direction
-                && global_player_state.direction != get_reversed_direction(movement_direction))
+        if (grabbing.is_some() || grabbed.is_some() || self.global_player_state.grabbing)
+            && (self.global_player_state.direction != direction
+                && self.global_player_state.direction != get_reversed_direction(direction))
         {
-            continue;
+            return Err(BlockedReason::GrabDirectionMismatch);
         }
 
-        let player_grid_coords = grid_coords_query.get(entity).unwrap();
-        let player_destination = *player_grid_coords
-            + match movement_direction {
+        let Ok(grid_coords) = self.grid_coords_query.get(entity) else {
+            return Err(BlockedReason::Wall);
+        };
+
+        // with gravity, only players standing on a ladder can go up
+        if self.level_rules.gravity
+            && direction == Direction::North
+            && (block.is_some()
+                || grabbing.is_some()
+                || self.walls.tile(entity, grid_coords) != Some(Tile::Ladder))
+        {
+            return Err(BlockedReason::NoLadder);
+        }
+
+        let destination = *grid_coords
+            + match direction {
                 Direction::North => GridCoords::new(0, 1),
                 Direction::East => GridCoords::new(1, 0),
                 Direction::South => GridCoords::new(0, -1),
                 Direction::West => GridCoords::new(-1, 0),
                 _ => GridCoords::new(0, 0),
             };
-        let can_move = can_move(
-            &grid_coords_query,
-            movement_direction,
-            movable,
-            &movible_query,
-            &level_walls,
-            &block_query,
-        );
-        if can_move && !level_walls.in_wall(&player_destination) {
+        if self.walls.in_wall(entity, &destination) {
+            return Err(BlockedReason::Wall);
+        }
+        self.can_move(entity, destination, direction)
+    }
+
+    // walks the line of movables in front of the mover, each of them has to be
+    // free to take a step as well, and `push_limit` caps how long the line gets
+    fn can_move(
+        &self,
+        entity: Entity,
+        destination: GridCoords,
+        direction: Direction,
+    ) -> Result<(), BlockedReason> {
+        let Some(offset) = get_movement_coords_from_direction(direction) else {
+            return Ok(());
+        };
+        let level_walls = self.walls.of(entity);
+        let mut push_limit = self.level_rules.push_limit;
+        let mut neighbor_coords = destination;
+        while let Some(neighbor) = self.occupancy.get(level_walls.to_world(neighbor_coords)) {
+            if neighbor.is_block() && !self.is_grabbed(neighbor.entity) {
+                return Err(BlockedReason::UnpushableBlock);
+            }
+            if push_limit == Some(0) {
+                return Err(BlockedReason::ChainTooLong);
+            }
+            push_limit = push_limit.map(|push_limit| push_limit - 1);
+            neighbor_coords = neighbor_coords + offset;
+            if self.walls.in_wall(entity, &neighbor_coords) {
+                return Err(BlockedReason::Wall);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_grabbed(&self, entity: Entity) -> bool {
+        self.mover_query
+            .get(entity)
+            .map_or(false, |(_, grabbed, _)| grabbed.is_some())
+    }
+
+    /// Whether a block moving in `direction` is pushed rather than pulled.
+    pub fn is_push(&self, direction: Direction) -> bool {
+        direction == self.global_player_state.direction
+    }
+}
+
+pub fn handle_move_player(
+    mut commands: Commands,
+    moving_query: Query<Entity, With<IsMoving>>,
+    player_query: Query<(Entity, Option<&Block>), (With<Movable>, Without<IsMoving>)>,
+    move_rules: MoveRules,
+    actions: Res<Actions>,
+    mut ev_player_move: EventWriter<PlayerMoveEvent>,
+    mut ev_push_move: EventWriter<PushMoveEvent>,
+    mut ev_pull_move: EventWriter<PullMoveEvent>,
+    mut ev_turn: EventWriter<TurnEvent>,
+    mut ev_move_blocked: EventWriter<MoveBlocked>,
+) {
+    // if anything is moving (or falling), don't move any players
+    // this is very important because otherwise the will move
+    // out of sync and have a chance of merging into one space
+    if moving_query.iter().count() > 0 {
+        return;
+    }
+    let movement_direction = actions.direction();
+    if movement_direction == Direction::None {
+        return;
+    }
+    let mut any_moved = false;
+    for (entity, block) in player_query.iter() {
+        if !move_rules.is_mover(entity) {
+            continue;
+        }
+
+        if let Err(reason) = move_rules.check(entity, movement_direction) {
+            ev_move_blocked.send(MoveBlocked {
+                entity,
+                direction: movement_direction,
+                reason,
+            });
+        } else {
             commands.entity(entity).insert(IsMoving);
             ev_player_move.send(PlayerMoveEvent(entity, movement_direction));
+            if block.is_some() {
+                if move_rules.is_push(movement_direction) {
+                    ev_push_move.send(PushMoveEvent(entity, movement_direction));
+                } else {
+                    ev_pull_move.send(PullMoveEvent(entity, movement_direction));
+                }
+            }
+            any_moved = true;
         }
     }
+    if any_moved {
+        ev_turn.send(TurnEvent(movement_direction));
+    }
 }
 
 pub fn handle_move_player_event(
     mut ev_player_move: EventReader<PlayerMoveEvent>,
     mut grid_coords_query: Query<&mut GridCoords, With<Movable>>,
+    mut ev_game_error: EventWriter<GameError>,
 ) {
     for ev in ev_player_move.iter() {
-        let mut player_grid_coords = grid_coords_query.get_mut(ev.0).unwrap();
+        let Ok(mut player_grid_coords) = grid_coords_query.get_mut(ev.0) else {
+            ev_game_error.send(GameError::MissingComponent(ev.0, "GridCoords"));
+            continue;
+        };
         let player_destination = *player_grid_coords
             + match ev.1 {
                 Direction::North => GridCoords::new(0, 1),
@@ -204,57 +317,10 @@ pub fn handle_move_player_event(
     }
 }
 
-fn can_move(
-    grid_coords_query: &Query<&GridCoords, With<Movable>>,
-    direction: Direction,
-    movable: &Movable,
-    movable_query: &Query<&Movable>,
-    level_walls: &Res<LevelWalls>,
-    block_query: &Query<&Block, Without<Grabbed>>,
-) -> bool {
-    let neighbor_entity = match direction {
-        Direction::North => movable.north_neighbor,
-        Direction::East => movable.east_neighbor,
-        Direction::South => movable.south_neighbor,
-        Direction::West => movable.west_neighbor,
-        _ => None,
-    };
-    if neighbor_entity.is_none() {
-        return true;
-    }
-    let neighbor_entity = neighbor_entity.unwrap();
-    let is_neighbor_block = block_query.get(neighbor_entity).is_ok();
-    if is_neighbor_block {
-        return false;
-    }
-    let neighbor_movable = movable_query.get(neighbor_entity).unwrap();
-    let neighbor_grid_coords = grid_coords_query.get(neighbor_entity).unwrap();
-    let neighbor_destination = *neighbor_grid_coords
-        + match direction {
-            Direction::North => GridCoords::new(0, 1),
-            Direction::East => GridCoords::new(1, 0),
-            Direction::South => GridCoords::new(0, -1),
-            Direction::West => GridCoords::new(-1, 0),
-            _ => GridCoords::new(0, 0),
-        };
-    if level_walls.in_wall(&neighbor_destination) {
-        return false;
-    }
-
-    return can_move(
-        grid_coords_query,
-        direction,
-        neighbor_movable,
-        movable_query,
-        level_walls,
-        block_query,
-    );
-}
-
 pub fn turn_player_from_input(
     mut player_query: Query<(&mut Player, Option<&Grabbing>), Without<IsMoving>>,
     moving_player_query: Query<Entity, With<IsMoving>>,
-    input: Res<Input<KeyCode>>,
+    actions: Res<Actions>,
     global_player_state: Res<GlobalPlayerState>,
 ) {
     // if any player is moving, don't change facing of any players
@@ -267,19 +333,14 @@ pub fn turn_player_from_input(
         if grabbing.is_some() || global_player_state.grabbing {
             continue;
         }
-        if input.pressed(KeyCode::W) {
-            player.face_direction = Direction::North;
-        } else if input.pressed(KeyCode::A) {
-            player.face_direction = Direction::West;
-        } else if input.pressed(KeyCode::S) {
-            player.face_direction = Direction::South;
-        } else if input.pressed(KeyCode::D) {
-            player.face_direction = Direction::East;
+        let direction = actions.direction();
+        if direction != Direction::None && player.face_direction != direction {
+            player.face_direction = direction;
         }
     }
 }
 
-fn get_neighbor_direction(origin: &GridCoords, neighbor: &GridCoords) -> Direction {
+pub fn get_neighbor_direction(origin: &GridCoords, neighbor: &GridCoords) -> Direction {
     if *origin + GridCoords::new(0, 1) == *neighbor {
         return Direction::North;
     } else if *origin + GridCoords::new(1, 0) == *neighbor {
@@ -316,21 +377,28 @@ impl Default for GlobalPlayerState {
 pub fn grab_from_held_input(
     mut commands: Commands,
     player_query: Query<(Entity, &Player, &GridCoords), Without<IsMoving>>,
-    pushable_query: Query<(Entity, &GridCoords), (Without<IsMoving>, With<Pushable>)>,
-    input: Res<Input<KeyCode>>,
+    pushable_query: Query<(), (Without<IsMoving>, With<Pushable>)>,
+    occupancy: Res<Occupancy>,
+    walls: EntityWalls,
+    actions: Res<Actions>,
     mut global_player_state: ResMut<GlobalPlayerState>,
 ) {
-    if input.just_pressed(KeyCode::Space) {
-        for (entity, pushable_grid_coords) in pushable_query.iter() {
-            for (player_entity, player, player_grid_coords) in player_query.iter() {
-                if get_neighbor_direction(player_grid_coords, pushable_grid_coords)
-                    == player.face_direction
-                {
-                    commands.entity(entity).insert(Grabbed);
-                    commands.entity(player_entity).insert(Grabbing);
-                    global_player_state.direction = player.face_direction;
-                    global_player_state.grabbing = true;
-                }
+    if actions.grab_pressed {
+        for (player_entity, player, player_grid_coords) in player_query.iter() {
+            let Some(offset) = get_movement_coords_from_direction(player.face_direction) else {
+                continue;
+            };
+            let facing = walls
+                .of(player_entity)
+                .to_world(*player_grid_coords + offset);
+            let Some(occupant) = occupancy.get(facing) else {
+                continue;
+            };
+            if pushable_query.contains(occupant.entity) {
+                commands.entity(occupant.entity).insert(Grabbed);
+                commands.entity(player_entity).insert(Grabbing);
+                global_player_state.direction = player.face_direction;
+                global_player_state.grabbing = true;
             }
         }
     }
@@ -339,10 +407,10 @@ pub fn grab_from_held_input(
 pub fn ungrab_from_release_input(
     mut commands: Commands,
     movable_query: Query<Entity, With<Movable>>,
-    input: Res<Input<KeyCode>>,
+    actions: Res<Actions>,
     mut global_player_state: ResMut<GlobalPlayerState>,
 ) {
-    if input.just_released(KeyCode::Space) {
+    if actions.grab_released {
         for entity in movable_query.iter() {
             commands.entity(entity).remove::<Grabbed>();
             commands.entity(entity).remove::<Grabbing>();
@@ -351,56 +419,7 @@ pub fn ungrab_from_release_input(
     }
 }
 
-pub fn update_player_facing_direction(
-    mut player_query: Query<(&mut Player, &mut TextureAtlasSprite), Changed<Player>>,
-) {
-    for (player, mut sprite) in player_query.iter_mut() {
-        match player.face_direction {
-            Direction::North => sprite.index = 2,
-            Direction::East => {
-                sprite.index = 1;
-                sprite.flip_x = true;
-            }
-            Direction::South => sprite.index = 0,
-            Direction::West => {
-                sprite.index = 1;
-                sprite.flip_x = false;
-            }
-            _ => {
-                // default to facing right
-                sprite.index = 1;
-                sprite.flip_x = true;
-            }
-        }
-    }
-}
-
-pub fn highlight_grabbed(mut grabbed_query: Query<&mut TextureAtlasSprite, With<Grabbed>>) {
-    for mut sprite in grabbed_query.iter_mut() {
-        sprite.color = Color::rgb(0.0, 1.0, 0.0);
-    }
-}
-
-pub fn unhighlight_grabbed(mut grabbed_query: Query<&mut TextureAtlasSprite, Without<Grabbed>>) {
-    for mut sprite in grabbed_query.iter_mut() {
-        sprite.color = Color::rgb(1.0, 1.0, 1.0);
-    }
-}
-
-fn get_movement_coords_from_input(input: &Res<Input<KeyCode>>) -> Option<GridCoords> {
-    if input.pressed(KeyCode::W) {
-        return Some(GridCoords::new(0, 1));
-    } else if input.pressed(KeyCode::A) {
-        return Some(GridCoords::new(-1, 0));
-    } else if input.pressed(KeyCode::S) {
-        return Some(GridCoords::new(0, -1));
-    } else if input.pressed(KeyCode::D) {
-        return Some(GridCoords::new(1, 0));
-    }
-    return None;
-}
-
-fn get_movement_coords_from_direction(direction: Direction) -> Option<GridCoords> {
+pub fn get_movement_coords_from_direction(direction: Direction) -> Option<GridCoords> {
     if direction == Direction::North {
         return Some(GridCoords::new(0, 1));
     } else if direction == Direction::West {
@@ -414,19 +433,6 @@ fn get_movement_coords_from_direction(direction: Direction) -> Option<GridCoords
 }
 //recursive function to see if neighbor can move
 
-fn get_movement_direction_from_input(input: &Res<Input<KeyCode>>) -> Direction {
-    if input.pressed(KeyCode::W) {
-        return Direction::North;
-    } else if input.pressed(KeyCode::A) {
-        return Direction::West;
-    } else if input.pressed(KeyCode::S) {
-        return Direction::South;
-    } else if input.pressed(KeyCode::D) {
-        return Direction::East;
-    }
-    return Direction::None;
-}
-
 fn get_reversed_direction(direction: Direction) -> Direction {
     if direction == Direction::North {
         return Direction::South;
@@ -439,22 +445,3 @@ fn get_reversed_direction(direction: Direction) -> Direction {
     }
     return Direction::None;
 }
-
-pub fn check_goal(
-    level_selection: ResMut<LevelSelection>,
-    players: Query<&GridCoords, (With<Player>, Changed<GridCoords>)>,
-    goals: Query<&GridCoords, With<Goal>>,
-) {
-    if players
-        .iter()
-        .zip(goals.iter())
-        .any(|(player_grid_coords, goal_grid_coords)| player_grid_coords == goal_grid_coords)
-    {
-        let indices = match level_selection.into_inner() {
-            LevelSelection::Indices(indices) => indices,
-            _ => panic!("level selection should always be Indices in this game"),
-        };
-
-        indices.level += 1;
-    }
-}
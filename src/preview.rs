@@ -0,0 +1,159 @@
+use crate::hazards::PlayerDeath;
+use crate::pause::LeaveLevel;
+use crate::player::{
+    get_movement_coords_from_direction, handle_move_player_event, Direction, Movable, MoveRules,
+};
+use crate::save::SaveData;
+use crate::tile_map::{IsMoving, GRID_SIZE};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+pub struct PreviewPlugin;
+
+// An assist that shows, for every direction, where each player and grabbed
+// block would end up, or a red cross where it would be blocked. It asks the
+// same `MoveRules` as the real moves without touching any `GridCoords`.
+// Falling after the move is not predicted.
+impl Plugin for PreviewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MovePreview>()
+            .add_systems(
+                Update,
+                (
+                    update_move_preview.after(handle_move_player_event),
+                    draw_blocked_crosses.after(update_move_preview),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(LeaveLevel, cleanup_move_preview);
+    }
+}
+
+const GHOST_ALPHA: f32 = 0.35;
+const BLOCKED_COLOR: Color = Color::rgba(1.0, 0.2, 0.2, 0.8);
+/// Half the size of the cross drawn over a blocked destination.
+const CROSS_SIZE: f32 = 4.;
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::North,
+    Direction::East,
+    Direction::South,
+    Direction::West,
+];
+
+#[derive(Component)]
+struct MoveGhost;
+
+/// One predicted step: who, which way, and whether it goes through.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Outcome {
+    entity: Entity,
+    direction: Direction,
+    /// World position of the cell the step ends in.
+    destination: Vec3,
+    blocked: bool,
+}
+
+/// The current prediction. Ghosts are only respawned when it changes.
+#[derive(Resource, Default)]
+struct MovePreview(Vec<Outcome>);
+
+fn predict(
+    move_rules: &MoveRules,
+    mover_query: &Query<(Entity, &GlobalTransform), With<Movable>>,
+) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    for direction in DIRECTIONS {
+        let Some(offset) = get_movement_coords_from_direction(direction) else {
+            continue;
+        };
+        let offset = Vec3::new(offset.x as f32, offset.y as f32, 0.) * GRID_SIZE as f32;
+        for (entity, transform) in mover_query.iter() {
+            if !move_rules.is_mover(entity) {
+                continue;
+            }
+            outcomes.push(Outcome {
+                entity,
+                direction,
+                destination: transform.translation() + offset,
+                blocked: move_rules.check(entity, direction).is_err(),
+            });
+        }
+    }
+    outcomes
+}
+
+fn update_move_preview(
+    mut commands: Commands,
+    save_data: Res<SaveData>,
+    move_rules: MoveRules,
+    mover_query: Query<(Entity, &GlobalTransform), With<Movable>>,
+    sprite_query: Query<(&TextureAtlasSprite, &Handle<TextureAtlas>)>,
+    moving_query: Query<(), With<IsMoving>>,
+    ghost_query: Query<Entity, With<MoveGhost>>,
+    player_death: Res<PlayerDeath>,
+    mut move_preview: ResMut<MovePreview>,
+) {
+    // nothing to predict mid-move, and the preview can be switched off any time
+    let outcomes =
+        if save_data.settings.move_preview && moving_query.is_empty() && !player_death.is_dead() {
+            predict(&move_rules, &mover_query)
+        } else {
+            Vec::new()
+        };
+    if outcomes == move_preview.0 {
+        return;
+    }
+    for entity in ghost_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for outcome in outcomes.iter().filter(|outcome| !outcome.blocked) {
+        let Ok((sprite, atlas)) = sprite_query.get(outcome.entity) else {
+            continue;
+        };
+        commands.spawn((
+            SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    index: sprite.index,
+                    flip_x: sprite.flip_x,
+                    color: sprite.color.with_a(GHOST_ALPHA),
+                    ..default()
+                },
+                texture_atlas: atlas.clone(),
+                transform: Transform::from_translation(outcome.destination),
+                ..default()
+            },
+            MoveGhost,
+        ));
+    }
+    move_preview.0 = outcomes;
+}
+
+// crosses are immediate mode gizmos, ghosts are sprites
+fn draw_blocked_crosses(mut gizmos: Gizmos, move_preview: Res<MovePreview>) {
+    for outcome in move_preview.0.iter().filter(|outcome| outcome.blocked) {
+        let center = outcome.destination.truncate();
+        gizmos.line_2d(
+            center - Vec2::splat(CROSS_SIZE),
+            center + Vec2::splat(CROSS_SIZE),
+            BLOCKED_COLOR,
+        );
+        gizmos.line_2d(
+            center + Vec2::new(-CROSS_SIZE, CROSS_SIZE),
+            center + Vec2::new(CROSS_SIZE, -CROSS_SIZE),
+            BLOCKED_COLOR,
+        );
+    }
+}
+
+fn cleanup_move_preview(
+    mut commands: Commands,
+    query: Query<Entity, With<MoveGhost>>,
+    mut move_preview: ResMut<MovePreview>,
+) {
+    move_preview.0.clear();
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
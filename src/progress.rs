@@ -0,0 +1,208 @@
+use crate::gravity::LevelRules;
+use crate::hazards::PlayerDeath;
+use crate::lasers::Receiver;
+use crate::player::{Movable, Player};
+use crate::save::SaveData;
+use crate::score::{Medal, MoveCounter};
+use crate::tile_map::{EntityWalls, Goal, IsMoving};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use std::collections::HashSet;
+use std::time::Duration;
+
+pub struct ProgressPlugin;
+
+impl Plugin for ProgressPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelClock>()
+            .add_event::<LevelCompleted>()
+            .add_systems(
+                Update,
+                (
+                    tick_level_clock,
+                    check_level_complete,
+                    complete_level.after(check_level_complete),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Sent once every goal is covered and every receiver is lit.
+#[derive(Event)]
+pub struct LevelCompleted {
+    pub level_iid: String,
+    pub moves: u32,
+    pub pushes: u32,
+    pub pulls: u32,
+    pub time: Duration,
+    pub medal: Option<Medal>,
+}
+
+/// Time spent in the current attempt, reset whenever the level (re)spawns.
+#[derive(Default, Resource)]
+pub struct LevelClock {
+    pub elapsed: Duration,
+}
+
+pub fn tick_level_clock(
+    time: Res<Time>,
+    mut level_clock: ResMut<LevelClock>,
+    mut level_events: EventReader<LevelEvent>,
+) {
+    for level_event in level_events.iter() {
+        if let LevelEvent::Spawned(_) = level_event {
+            level_clock.elapsed = Duration::ZERO;
+        }
+    }
+    level_clock.elapsed += time.delta();
+}
+
+// grid coords change when a move starts, so wait for the tweens to
+// finish before deciding whether the level is done. The level the players
+// are in is the one checked, movables from a neighbouring level count too.
+pub fn check_level_complete(
+    mut pending: Local<bool>,
+    changed_query: Query<(), (Changed<GridCoords>, With<Movable>)>,
+    moving_query: Query<(), With<IsMoving>>,
+    player_query: Query<Entity, With<Player>>,
+    goal_query: Query<(Entity, &GridCoords), With<Goal>>,
+    movable_query: Query<(Entity, &GridCoords), With<Movable>>,
+    receiver_query: Query<(Entity, &Receiver)>,
+    walls: EntityWalls,
+    player_death: Res<PlayerDeath>,
+    move_counter: Res<MoveCounter>,
+    level_rules: Res<LevelRules>,
+    level_clock: Res<LevelClock>,
+    mut ev_level_completed: EventWriter<LevelCompleted>,
+) {
+    if !changed_query.is_empty() {
+        *pending = true;
+    }
+    if !*pending || !moving_query.is_empty() || player_death.is_dead() {
+        return;
+    }
+    *pending = false;
+
+    let Some(level_iid) = player_query
+        .iter()
+        .find_map(|entity| walls.level_iid(entity))
+    else {
+        return;
+    };
+    let in_level = |entity: Entity| walls.level_iid(entity) == Some(level_iid);
+    let goals: Vec<GridCoords> = goal_query
+        .iter()
+        .filter(|(entity, _)| in_level(*entity))
+        .map(|(entity, grid_coords)| walls.of(entity).to_world(*grid_coords))
+        .collect();
+    if goals.is_empty() {
+        return;
+    }
+    let covered: HashSet<GridCoords> = movable_query
+        .iter()
+        .map(|(entity, grid_coords)| walls.of(entity).to_world(*grid_coords))
+        .collect();
+    let goals_covered = goals.iter().all(|goal| covered.contains(goal));
+    let receivers_lit = receiver_query
+        .iter()
+        .filter(|(entity, _)| in_level(*entity))
+        .all(|(_, receiver)| receiver.lit);
+    if goals_covered && receivers_lit {
+        ev_level_completed.send(LevelCompleted {
+            level_iid: level_iid.get().clone(),
+            moves: move_counter.moves,
+            pushes: move_counter.pushes,
+            pulls: move_counter.pulls,
+            time: level_clock.elapsed,
+            medal: Medal::for_moves(move_counter.moves, level_rules.par),
+        });
+    }
+}
+
+pub fn complete_level(
+    mut ev_level_completed: EventReader<LevelCompleted>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for level_completed in ev_level_completed.iter() {
+        info!(
+            "Completed level {} in {} moves ({:?})",
+            level_completed.level_iid, level_completed.moves, level_completed.medal
+        );
+        next_state.set(GameState::LevelComplete);
+    }
+}
+
+/// The first level is always open, every other one as soon as it or one of
+/// its neighbours in the LDtk world has been completed.
+pub fn is_level_unlocked<'a>(
+    is_first: bool,
+    level_iid: &str,
+    neighbour_iids: impl IntoIterator<Item = &'a str>,
+    save_data: &SaveData,
+) -> bool {
+    is_first
+        || save_data.is_completed(level_iid)
+        || neighbour_iids
+            .into_iter()
+            .any(|neighbour_iid| save_data.is_completed(neighbour_iid))
+}
+
+/// The level to go on with after completing `level_iid`: an open, unfinished
+/// playable level of the same project, preferring the completed level's
+/// neighbours. Runs before the completion is saved, so it counts as done.
+pub fn next_level(
+    ldtk_project: &LdtkProject,
+    level_iid: &str,
+    save_data: &SaveData,
+) -> Option<String> {
+    let completed = ldtk_project
+        .iter_raw_levels()
+        .find(|level| level.iid == level_iid)?;
+    let is_neighbour = |level: &ldtk::Level| {
+        completed
+            .neighbours
+            .iter()
+            .any(|neighbour| neighbour.level_iid == level.iid)
+    };
+    let candidates: Vec<&ldtk::Level> = ldtk_project
+        .iter_raw_levels()
+        .filter(|level| is_playable(level))
+        .enumerate()
+        .filter(|(index, level)| {
+            level.iid != level_iid
+                && !save_data.is_completed(&level.iid)
+                && (is_neighbour(*level)
+                    || is_level_unlocked(
+                        *index == 0,
+                        &level.iid,
+                        level
+                            .neighbours
+                            .iter()
+                            .map(|neighbour| neighbour.level_iid.as_str()),
+                        save_data,
+                    ))
+        })
+        .map(|(_, level)| level)
+        .collect();
+    candidates
+        .iter()
+        .find(|level| is_neighbour(**level))
+        .or_else(|| candidates.first())
+        .map(|level| level.iid.clone())
+}
+
+/// Levels without a player only make up the scenery around the playable ones.
+pub fn is_playable(level: &ldtk::Level) -> bool {
+    level
+        .layer_instances
+        .iter()
+        .flatten()
+        .any(|layer_instance| {
+            layer_instance
+                .entity_instances
+                .iter()
+                .any(|entity_instance| entity_instance.identifier == "Player")
+        })
+}
@@ -0,0 +1,352 @@
+use crate::actions::{set_movement_actions, Actions};
+use crate::error::GameError;
+use crate::hazards::PlayerDeath;
+use crate::history::{restart_from_input, undo_from_input, RestartEvent, UndoEvent};
+use crate::pause::LeaveLevel;
+use crate::player::{
+    grab_from_held_input, handle_move_player, turn_player_from_input, ungrab_from_release_input,
+    Direction, Player, TurnEvent,
+};
+use crate::save::{write_atomically, SaveFile};
+use crate::tile_map::IsMoving;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecorder>()
+            .add_systems(OnEnter(GameState::Menu), start_requested_replay)
+            .add_systems(
+                Update,
+                (
+                    start_recording,
+                    record_actions
+                        .after(handle_move_player)
+                        .after(turn_player_from_input)
+                        .after(grab_from_held_input)
+                        .after(ungrab_from_release_input)
+                        .after(undo_from_input)
+                        .after(restart_from_input)
+                        .run_if(not(resource_exists::<ReplayPlayback>())),
+                    feed_replay_actions
+                        .after(set_movement_actions)
+                        .before(handle_move_player)
+                        .before(turn_player_from_input)
+                        .before(grab_from_held_input)
+                        .before(ungrab_from_release_input)
+                        .before(undo_from_input)
+                        .before(restart_from_input)
+                        .run_if(resource_exists::<ReplayPlayback>()),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(LeaveLevel, (store_recording, stop_playback));
+    }
+}
+
+/// Bump this whenever the layout of `Replay` changes.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// Path of a replay to play back right after loading, set by `--replay <file>`.
+#[derive(Resource, Clone, Debug)]
+pub struct ReplayFile(pub PathBuf);
+
+impl ReplayFile {
+    pub fn from_args() -> Option<ReplayFile> {
+        let mut args = std::env::args().skip_while(|arg| arg != "--replay");
+        args.next()?;
+        args.next().map(|path| ReplayFile(PathBuf::from(path)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ReplayAction {
+    /// A direction input that either moved the players or turned them around.
+    Move(Direction),
+    Grab,
+    Release,
+    Undo,
+    Restart,
+}
+
+/// An action and the number of turns taken before it, undone turns included.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ReplayEntry(pub u32, pub ReplayAction);
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Replay {
+    pub version: u32,
+    pub level_iid: String,
+    pub entries: Vec<ReplayEntry>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let replay: Replay = ron::from_str(&contents).map_err(|error| error.to_string())?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "replay version {} is not supported, expected {REPLAY_VERSION}",
+                replay.version
+            ));
+        }
+        Ok(replay)
+    }
+
+    pub fn store(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::to_string(self).map_err(|error| error.to_string())?;
+        write_atomically(path, &contents)
+    }
+}
+
+#[derive(Default, Resource)]
+pub struct ReplayRecorder {
+    replay: Option<Replay>,
+    turns: u32,
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    cursor: usize,
+    turns: u32,
+    waiting_for_spawn: bool,
+}
+
+fn start_requested_replay(
+    mut commands: Commands,
+    replay_file: Option<Res<ReplayFile>>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(replay_file) = replay_file else {
+        return;
+    };
+    commands.remove_resource::<ReplayFile>();
+    match Replay::load(&replay_file.0) {
+        Ok(replay) => {
+            info!("Playing back replay {:?}", replay_file.0);
+            *level_selection = LevelSelection::iid(replay.level_iid.clone());
+            commands.insert_resource(ReplayPlayback {
+                replay,
+                cursor: 0,
+                turns: 0,
+                waiting_for_spawn: true,
+            });
+            next_state.set(GameState::Playing);
+        }
+        Err(error) => warn!("Failed to load replay {:?}: {error}", replay_file.0),
+    }
+}
+
+fn start_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    mut level_events: EventReader<LevelEvent>,
+) {
+    for level_event in level_events.iter() {
+        if let LevelEvent::Spawned(level_iid) = level_event {
+            // restarts keep recording into the same attempt
+            if recorder.replay.is_none() {
+                recorder.replay = Some(Replay {
+                    version: REPLAY_VERSION,
+                    level_iid: level_iid.get().clone(),
+                    entries: Vec::new(),
+                });
+                recorder.turns = 0;
+            }
+        }
+    }
+}
+
+// undos and restarts are taken from their events rather than the input, so the
+// ones sent by the pause menu, the deadlock undo and a death are recorded as well
+pub fn record_actions(
+    mut recorder: ResMut<ReplayRecorder>,
+    actions: Res<Actions>,
+    mut turns: EventReader<TurnEvent>,
+    mut ev_undo: EventReader<UndoEvent>,
+    mut ev_restart: EventReader<RestartEvent>,
+    turned_player_query: Query<(), Changed<Player>>,
+) {
+    let turn_taken = turns.iter().count() > 0;
+    let recorder = recorder.as_mut();
+    let Some(replay) = recorder.replay.as_mut() else {
+        return;
+    };
+    let direction = actions.direction();
+    if direction != Direction::None && (turn_taken || !turned_player_query.is_empty()) {
+        replay
+            .entries
+            .push(ReplayEntry(recorder.turns, ReplayAction::Move(direction)));
+    }
+    if turn_taken {
+        recorder.turns += 1;
+    }
+    for (pressed, action) in [
+        (actions.grab_pressed, ReplayAction::Grab),
+        (actions.grab_released, ReplayAction::Release),
+    ] {
+        if pressed {
+            replay.entries.push(ReplayEntry(recorder.turns, action));
+        }
+    }
+    for _ in ev_undo.iter() {
+        replay
+            .entries
+            .push(ReplayEntry(recorder.turns, ReplayAction::Undo));
+    }
+    for _ in ev_restart.iter() {
+        replay
+            .entries
+            .push(ReplayEntry(recorder.turns, ReplayAction::Restart));
+    }
+}
+
+fn store_recording(mut recorder: ResMut<ReplayRecorder>, save_file: Res<SaveFile>) {
+    let Some(replay) = recorder.replay.take() else {
+        return;
+    };
+    let Some(save_path) = save_file.path.as_ref() else {
+        return;
+    };
+    if replay.entries.is_empty() {
+        return;
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let path = save_path
+        .with_file_name("replays")
+        .join(format!("{}-{timestamp}.ron", replay.level_iid));
+    match replay.store(&path) {
+        Ok(()) => info!("Stored replay {path:?}"),
+        Err(error) => warn!("Failed to store replay {path:?}: {error}"),
+    }
+}
+
+fn stop_playback(mut commands: Commands) {
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+// feeds one action per idle frame through the same `Actions` the keyboard
+// would fill in, so playback runs through the regular movement systems
+pub fn feed_replay_actions(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    mut actions: ResMut<Actions>,
+    mut level_events: EventReader<LevelEvent>,
+    mut turns: EventReader<TurnEvent>,
+    moving_query: Query<(), With<IsMoving>>,
+    player_query: Query<(), With<Player>>,
+    player_death: Res<PlayerDeath>,
+    mut ev_game_error: EventWriter<GameError>,
+) {
+    *actions = Actions::default();
+    playback.turns += turns.iter().count() as u32;
+    if level_events
+        .iter()
+        .any(|level_event| matches!(level_event, LevelEvent::Spawned(_)))
+    {
+        playback.waiting_for_spawn = false;
+    }
+    if playback.waiting_for_spawn || !moving_query.is_empty() || player_query.is_empty() {
+        return;
+    }
+
+    let Some(ReplayEntry(turn, action)) = playback.replay.entries.get(playback.cursor).copied()
+    else {
+        info!("Replay finished");
+        commands.remove_resource::<ReplayPlayback>();
+        return;
+    };
+    // only undo and restart are accepted while the death animation plays,
+    // everything else waits for the automatic restart
+    if player_death.is_dead() && !matches!(action, ReplayAction::Undo | ReplayAction::Restart) {
+        return;
+    }
+    // feeding on would only pile more mistakes onto the first one
+    if turn != playback.turns {
+        ev_game_error.send(GameError::ReplayOutOfSync {
+            level_iid: playback.replay.level_iid.clone(),
+            expected: turn,
+            taken: playback.turns,
+        });
+        commands.remove_resource::<ReplayPlayback>();
+        return;
+    }
+    playback.cursor += 1;
+    match action {
+        ReplayAction::Move(direction) => {
+            actions.player_movement = match direction {
+                Direction::North => Some(Vec2::Y),
+                Direction::East => Some(Vec2::X),
+                Direction::South => Some(Vec2::NEG_Y),
+                Direction::West => Some(Vec2::NEG_X),
+                Direction::None => None,
+            };
+        }
+        ReplayAction::Grab => actions.grab_pressed = true,
+        ReplayAction::Release => actions.grab_released = true,
+        ReplayAction::Undo => actions.undo = true,
+        ReplayAction::Restart => {
+            actions.restart = true;
+            playback.waiting_for_spawn = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every test gets a directory of its own, they run in parallel
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sbpg-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn replay(version: u32) -> Replay {
+        Replay {
+            version,
+            level_iid: "level".to_string(),
+            entries: vec![
+                ReplayEntry(0, ReplayAction::Move(Direction::East)),
+                ReplayEntry(1, ReplayAction::Grab),
+                ReplayEntry(1, ReplayAction::Move(Direction::West)),
+                ReplayEntry(2, ReplayAction::Undo),
+                ReplayEntry(2, ReplayAction::Restart),
+            ],
+        }
+    }
+
+    #[test]
+    fn store_and_load_round_trip() {
+        let dir = temp_dir("replay-round-trip");
+        let path = dir.join("replays").join("level.ron");
+        replay(REPLAY_VERSION).store(&path).unwrap();
+        assert_eq!(Replay::load(&path).unwrap(), replay(REPLAY_VERSION));
+        let files: Vec<PathBuf> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files, vec![path]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_other_versions() {
+        let dir = temp_dir("replay-version");
+        let path = dir.join("level.ron");
+        replay(REPLAY_VERSION + 1).store(&path).unwrap();
+        assert!(Replay::load(&path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,473 @@
+use crate::gravity::LevelRules;
+use crate::loading::LevelAssets;
+use crate::menu::ButtonColors;
+use crate::progress::{check_level_complete, next_level, LevelCompleted};
+use crate::save::{record_completed_level, LevelRecord, SaveData};
+use crate::score::Medal;
+use crate::tile_map::Goal;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+pub struct ResultsPlugin;
+
+// Completing a level moves to `GameState::LevelComplete`, which keeps the level on
+// screen but stops all gameplay systems. The results panel waits for a choice and
+// a fade to black covers the switch to whatever comes next.
+impl Plugin for ResultsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ResultsFocus>()
+            .add_systems(
+                Update,
+                capture_level_result
+                    .after(check_level_complete)
+                    .before(record_completed_level)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                OnEnter(GameState::LevelComplete),
+                (setup_results, spawn_celebration),
+            )
+            .add_systems(
+                Update,
+                (
+                    navigate_results,
+                    click_results_button,
+                    update_results_button_colors
+                        .after(navigate_results)
+                        .after(click_results_button),
+                    update_celebration,
+                )
+                    .run_if(in_state(GameState::LevelComplete)),
+            )
+            .add_systems(Update, update_fade)
+            .add_systems(
+                OnExit(GameState::LevelComplete),
+                (cleanup_results, cleanup_celebration),
+            );
+    }
+}
+
+const FADE_SECONDS: f32 = 0.4;
+const PARTICLES_PER_GOAL: usize = 12;
+const PARTICLE_SPEED: f32 = 60.;
+const PARTICLE_GRAVITY: f32 = -120.;
+const PARTICLE_SECONDS: f32 = 1.2;
+const PARTICLE_COLORS: [Color; 3] = [
+    Color::rgb(1.0, 0.85, 0.2),
+    Color::rgb(0.4, 1.0, 0.4),
+    Color::rgb(0.4, 0.7, 1.0),
+];
+
+/// The finished attempt, together with the record as it was before it.
+#[derive(Resource)]
+pub struct LevelResult {
+    pub level_iid: String,
+    pub moves: u32,
+    pub pushes: u32,
+    pub pulls: u32,
+    pub time: Duration,
+    pub medal: Option<Medal>,
+    pub par: Option<u32>,
+    pub previous: Option<LevelRecord>,
+    /// Where "Next" goes, none once every open level has been completed.
+    pub next_level: Option<String>,
+}
+
+impl LevelResult {
+    fn is_new_best(&self) -> bool {
+        self.previous
+            .as_ref()
+            .and_then(|record| record.best_moves)
+            .map_or(true, |best_moves| self.moves < best_moves)
+    }
+}
+
+#[derive(Default, Resource)]
+struct ResultsFocus(usize);
+
+#[derive(Component)]
+struct Results;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ResultsAction {
+    Next,
+    Retry,
+    LevelSelect,
+}
+
+const RESULTS_ACTIONS: [(ResultsAction, &str); 3] = [
+    (ResultsAction::Next, "Next"),
+    (ResultsAction::Retry, "Retry"),
+    (ResultsAction::LevelSelect, "Level Select"),
+];
+
+#[derive(Component)]
+struct ResultsButton {
+    index: usize,
+    action: ResultsAction,
+}
+
+#[derive(Component)]
+struct Particle {
+    velocity: Vec2,
+    timer: Timer,
+}
+
+/// Full screen black overlay. It fades in, runs its action while the screen
+/// is black and fades out again.
+#[derive(Component)]
+struct Fade {
+    timer: Timer,
+    action: Option<ResultsAction>,
+}
+
+fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs_f32();
+    format!("{}:{:04.1}", (seconds / 60.) as u32, seconds % 60.)
+}
+
+// runs before the save is updated, so the previous best is still known
+fn capture_level_result(
+    mut commands: Commands,
+    mut ev_level_completed: EventReader<LevelCompleted>,
+    level_rules: Res<LevelRules>,
+    save_data: Res<SaveData>,
+    level_assets: Res<LevelAssets>,
+    level_selection: Res<LevelSelection>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+) {
+    for level_completed in ev_level_completed.iter() {
+        let next_level = ldtk_project_assets
+            .get(&level_assets.project_for(&level_selection, &ldtk_project_assets))
+            .and_then(|ldtk_project| {
+                next_level(ldtk_project, &level_completed.level_iid, &save_data)
+            });
+        commands.insert_resource(LevelResult {
+            level_iid: level_completed.level_iid.clone(),
+            moves: level_completed.moves,
+            pushes: level_completed.pushes,
+            pulls: level_completed.pulls,
+            time: level_completed.time,
+            medal: level_completed.medal,
+            par: level_rules.par,
+            previous: save_data.record(&level_completed.level_iid).cloned(),
+            next_level,
+        });
+    }
+}
+
+fn setup_results(
+    mut commands: Commands,
+    level_result: Option<Res<LevelResult>>,
+    save_data: Res<SaveData>,
+    mut focus: ResMut<ResultsFocus>,
+) {
+    focus.0 = 0;
+    let mut lines = Vec::new();
+    if let Some(level_result) = level_result {
+        lines.push(match level_result.par {
+            Some(par) => format!("Moves: {}  Par: {par}", level_result.moves),
+            None => format!("Moves: {}", level_result.moves),
+        });
+        lines.push(format!(
+            "Pushes: {}  Pulls: {}",
+            level_result.pushes, level_result.pulls
+        ));
+        lines.push(format!("Time: {}", format_time(level_result.time)));
+        if let Some(medal) = level_result.medal {
+            lines.push(format!("Medal: {medal:?}"));
+        }
+        if level_result.is_new_best() {
+            lines.push("New personal best!".to_string());
+        } else if let Some(record) = save_data.record(&level_result.level_iid) {
+            let best_moves = record.best_moves.unwrap_or(level_result.moves);
+            let best_time = record.best_time.unwrap_or(level_result.time);
+            lines.push(format!(
+                "Best: {best_moves} moves, {}",
+                format_time(best_time)
+            ));
+        }
+    }
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.4).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            Results,
+        ))
+        .with_children(|children| {
+            children.spawn(TextBundle::from_section(
+                "Level Complete!",
+                TextStyle {
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+            for line in lines {
+                children.spawn(TextBundle::from_section(
+                    line,
+                    TextStyle {
+                        font_size: 24.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..default()
+                    },
+                ));
+            }
+            for (index, (action, label)) in RESULTS_ACTIONS.into_iter().enumerate() {
+                let button_colors = ButtonColors::default();
+                children
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(220.0),
+                                height: Val::Px(50.0),
+                                margin: UiRect::all(Val::Px(5.)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: button_colors.normal.into(),
+                            ..default()
+                        },
+                        button_colors,
+                        ResultsButton { index, action },
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            label,
+                            TextStyle {
+                                font_size: 30.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+// a burst from every goal, skipped with reduced motion
+fn spawn_celebration(
+    mut commands: Commands,
+    goal_query: Query<&GlobalTransform, With<Goal>>,
+    save_data: Res<SaveData>,
+) {
+    if save_data.settings.reduced_motion {
+        return;
+    }
+    for transform in goal_query.iter() {
+        let origin = transform.translation().truncate();
+        for index in 0..PARTICLES_PER_GOAL {
+            let angle = TAU * index as f32 / PARTICLES_PER_GOAL as f32;
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: PARTICLE_COLORS[index % PARTICLE_COLORS.len()],
+                        custom_size: Some(Vec2::splat(2.)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(origin.extend(50.)),
+                    ..default()
+                },
+                Particle {
+                    velocity: Vec2::from_angle(angle) * PARTICLE_SPEED,
+                    timer: Timer::from_seconds(PARTICLE_SECONDS, TimerMode::Once),
+                },
+            ));
+        }
+    }
+}
+
+fn update_celebration(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particle_query: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite)>,
+) {
+    for (entity, mut particle, mut transform, mut sprite) in particle_query.iter_mut() {
+        particle.timer.tick(time.delta());
+        if particle.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        particle.velocity.y += PARTICLE_GRAVITY * time.delta_seconds();
+        transform.translation += (particle.velocity * time.delta_seconds()).extend(0.);
+        sprite.color.set_a(particle.timer.percent_left());
+    }
+}
+
+fn start_fade(commands: &mut Commands, action: ResultsAction) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.0).into(),
+            z_index: ZIndex::Global(20),
+            ..default()
+        },
+        Fade {
+            timer: Timer::from_seconds(FADE_SECONDS, TimerMode::Once),
+            action: Some(action),
+        },
+    ));
+}
+
+fn apply_results_action(
+    action: ResultsAction,
+    next_level: Option<&str>,
+    level_selection: &mut LevelSelection,
+    next_state: &mut NextState<GameState>,
+) {
+    match action {
+        // leaving the results always tears the level down, so whichever level
+        // is selected spawns afresh
+        ResultsAction::Next => match next_level {
+            Some(level_iid) => {
+                *level_selection = LevelSelection::iid(level_iid.to_string());
+                next_state.set(GameState::Playing);
+            }
+            // nothing left to unlock, the hub is where the rest is
+            None => next_state.set(GameState::Overworld),
+        },
+        ResultsAction::Retry => next_state.set(GameState::Playing),
+        ResultsAction::LevelSelect => next_state.set(GameState::LevelSelect),
+    }
+}
+
+fn update_fade(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut fade_query: Query<(Entity, &mut Fade, &mut BackgroundColor)>,
+    level_result: Option<Res<LevelResult>>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (entity, mut fade, mut color) in fade_query.iter_mut() {
+        fade.timer.tick(time.delta());
+        let progress = fade.timer.percent();
+        match fade.action {
+            Some(action) => {
+                color.0.set_a(progress);
+                if fade.timer.finished() {
+                    apply_results_action(
+                        action,
+                        level_result
+                            .as_ref()
+                            .and_then(|level_result| level_result.next_level.as_deref()),
+                        &mut level_selection,
+                        &mut next_state,
+                    );
+                    fade.action = None;
+                    fade.timer.reset();
+                }
+            }
+            None => {
+                color.0.set_a(1. - progress);
+                if fade.timer.finished() {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+        }
+    }
+}
+
+fn navigate_results(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    fade_query: Query<(), With<Fade>>,
+    mut focus: ResMut<ResultsFocus>,
+) {
+    if !fade_query.is_empty() {
+        return;
+    }
+    let gamepad_pressed = |button_type| {
+        gamepads
+            .iter()
+            .any(|gamepad| gamepad_input.just_pressed(GamepadButton::new(gamepad, button_type)))
+    };
+
+    if keyboard_input.any_just_pressed([KeyCode::W, KeyCode::Up])
+        || gamepad_pressed(GamepadButtonType::DPadUp)
+    {
+        focus.0 = focus.0.saturating_sub(1);
+    } else if keyboard_input.any_just_pressed([KeyCode::S, KeyCode::Down])
+        || gamepad_pressed(GamepadButtonType::DPadDown)
+    {
+        focus.0 = (focus.0 + 1).min(RESULTS_ACTIONS.len() - 1);
+    } else if keyboard_input.any_just_pressed([KeyCode::Return, KeyCode::Space])
+        || gamepad_pressed(GamepadButtonType::South)
+    {
+        start_fade(&mut commands, RESULTS_ACTIONS[focus.0].0);
+    }
+}
+
+// mouse and touch both go through ui interactions
+fn click_results_button(
+    mut commands: Commands,
+    interaction_query: Query<(&Interaction, &ResultsButton), Changed<Interaction>>,
+    fade_query: Query<(), With<Fade>>,
+    mut focus: ResMut<ResultsFocus>,
+) {
+    if !fade_query.is_empty() {
+        return;
+    }
+    for (interaction, button) in interaction_query.iter() {
+        match *interaction {
+            Interaction::Pressed => {
+                focus.0 = button.index;
+                start_fade(&mut commands, button.action);
+            }
+            Interaction::Hovered => focus.0 = button.index,
+            Interaction::None => {}
+        }
+    }
+}
+
+fn update_results_button_colors(
+    focus: Res<ResultsFocus>,
+    mut button_query: Query<(&ResultsButton, &ButtonColors, &mut BackgroundColor)>,
+) {
+    for (button, button_colors, mut color) in button_query.iter_mut() {
+        let new_color = if button.index == focus.0 {
+            button_colors.hovered
+        } else {
+            button_colors.normal
+        };
+        if color.0 != new_color {
+            color.0 = new_color;
+        }
+    }
+}
+
+fn cleanup_results(mut commands: Commands, query: Query<Entity, With<Results>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn cleanup_celebration(mut commands: Commands, query: Query<Entity, With<Particle>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,316 @@
+use crate::highlight::HighlightStyle;
+use crate::progress::{check_level_complete, LevelCompleted};
+use crate::score::Medal;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_tweening::{EaseFunction, EaseMethod};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SaveFile::default_location())
+            .init_resource::<SaveData>()
+            .add_systems(OnEnter(GameState::Loading), load_save_data)
+            .add_systems(
+                Update,
+                record_completed_level
+                    .after(check_level_complete)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Bump this whenever the layout of `SaveData` changes and teach
+/// `SaveData::migrate` how to read the old one.
+pub const SAVE_VERSION: u32 = 1;
+
+/// Environment variable overriding where the save file lives.
+pub const SAVE_PATH_VAR: &str = "SBPG_SAVE_PATH";
+
+/// Location of the save file. `None` disables persistence, e.g. on the web.
+#[derive(Resource, Clone, Debug)]
+pub struct SaveFile {
+    pub path: Option<PathBuf>,
+}
+
+impl SaveFile {
+    pub fn default_location() -> Self {
+        let path = std::env::var_os(SAVE_PATH_VAR)
+            .map(PathBuf::from)
+            .or_else(|| dirs::data_dir().map(|dir| dir.join("sbpg").join("save.ron")));
+        SaveFile { path }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct LevelRecord {
+    pub completed: bool,
+    pub best_moves: Option<u32>,
+    pub best_time: Option<Duration>,
+    pub best_medal: Option<Medal>,
+    pub hints_used: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub music_volume: f64,
+    pub sfx_volume: f64,
+    pub move_duration_millis: u64,
+    pub reduced_motion: bool,
+    pub fullscreen: bool,
+    /// The window is `window_scale` times the 256 pixel playfield.
+    pub window_scale: u32,
+    pub highlight_style: HighlightStyle,
+    /// Assist showing where the next move in each direction would lead.
+    pub move_preview: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            music_volume: 0.5,
+            sfx_volume: 0.8,
+            move_duration_millis: 100,
+            reduced_motion: false,
+            fullscreen: false,
+            window_scale: 3,
+            highlight_style: HighlightStyle::Tint,
+            move_preview: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn move_duration(&self) -> Duration {
+        Duration::from_millis(self.move_duration_millis)
+    }
+
+    /// Reduced motion keeps the timing of every tween but snaps to the end
+    /// right away instead of sliding, squashing or bouncing there.
+    pub fn ease(&self, ease_function: EaseFunction) -> EaseMethod {
+        if self.reduced_motion {
+            EaseMethod::Discrete(0.)
+        } else {
+            ease_function.into()
+        }
+    }
+}
+
+/// Everything that survives between sessions, keyed by level iid.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SaveData {
+    pub version: u32,
+    pub levels: HashMap<String, LevelRecord>,
+    pub settings: Settings,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        SaveData {
+            version: SAVE_VERSION,
+            levels: HashMap::new(),
+            settings: Settings::default(),
+        }
+    }
+}
+
+impl SaveData {
+    pub fn is_completed(&self, level_iid: &str) -> bool {
+        self.levels
+            .get(level_iid)
+            .map_or(false, |record| record.completed)
+    }
+
+    pub fn record(&self, level_iid: &str) -> Option<&LevelRecord> {
+        self.levels.get(level_iid)
+    }
+
+    /// Marks the level as completed and keeps the better of the old and new scores.
+    pub fn complete_level(
+        &mut self,
+        level_iid: &str,
+        moves: u32,
+        time: Duration,
+        medal: Option<Medal>,
+    ) {
+        let record = self.levels.entry(level_iid.to_string()).or_default();
+        record.completed = true;
+        record.best_moves = Some(record.best_moves.map_or(moves, |best| best.min(moves)));
+        record.best_time = Some(record.best_time.map_or(time, |best| best.min(time)));
+        record.best_medal = record.best_medal.max(medal);
+    }
+
+    pub fn record_hint(&mut self, level_iid: &str) {
+        self.levels
+            .entry(level_iid.to_string())
+            .or_default()
+            .hints_used += 1;
+    }
+
+    fn migrate(mut self) -> Self {
+        // version 1 is the first format, there is nothing to migrate yet
+        self.version = SAVE_VERSION;
+        self
+    }
+
+    pub fn load(save_file: &SaveFile) -> Result<SaveData, String> {
+        let Some(path) = save_file.path.as_ref() else {
+            return Ok(SaveData::default());
+        };
+        if !path.exists() {
+            return Ok(SaveData::default());
+        }
+        let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let save_data: SaveData = ron::from_str(&contents).map_err(|error| error.to_string())?;
+        if save_data.version > SAVE_VERSION {
+            return Err(format!(
+                "save file version {} is newer than supported version {SAVE_VERSION}",
+                save_data.version
+            ));
+        }
+        Ok(save_data.migrate())
+    }
+
+    pub fn store(&self, save_file: &SaveFile) -> Result<(), String> {
+        let Some(path) = save_file.path.as_ref() else {
+            return Ok(());
+        };
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+        write_atomically(path, &contents)
+    }
+}
+
+/// Writes to a temporary file next to `path` first and renames it over the
+/// old file, so a crash mid-write never leaves a truncated file behind.
+pub fn write_atomically(path: &Path, contents: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+    }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    fs::write(&temp_path, contents).map_err(|error| error.to_string())?;
+    fs::rename(&temp_path, path).map_err(|error| error.to_string())
+}
+
+fn load_save_data(mut save_data: ResMut<SaveData>, save_file: Res<SaveFile>) {
+    match SaveData::load(&save_file) {
+        Ok(loaded) => *save_data = loaded,
+        Err(error) => warn!("Failed to load save file {:?}: {error}", save_file.path),
+    }
+}
+
+pub fn record_completed_level(
+    mut ev_level_completed: EventReader<LevelCompleted>,
+    mut save_data: ResMut<SaveData>,
+    save_file: Res<SaveFile>,
+) {
+    for level_completed in ev_level_completed.iter() {
+        save_data.complete_level(
+            &level_completed.level_iid,
+            level_completed.moves,
+            level_completed.time,
+            level_completed.medal,
+        );
+        if let Err(error) = save_data.store(&save_file) {
+            warn!("Failed to write save file {:?}: {error}", save_file.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every test gets a directory of its own, they run in parallel
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sbpg-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn store_and_load_round_trip_through_the_save_path_variable() {
+        let dir = temp_dir("round-trip");
+        let path = dir.join("save.ron");
+        std::env::set_var(SAVE_PATH_VAR, &path);
+        let save_file = SaveFile::default_location();
+        assert_eq!(save_file.path.as_ref(), Some(&path));
+
+        let mut save_data = SaveData::default();
+        save_data.complete_level("level", 12, Duration::from_secs(3), Some(Medal::Silver));
+        save_data.record_hint("level");
+        save_data.settings.window_scale = 2;
+        save_data.store(&save_file).unwrap();
+
+        let loaded = SaveData::load(&save_file).unwrap();
+        let record = loaded.record("level").unwrap();
+        assert!(record.completed);
+        assert_eq!(record.best_moves, Some(12));
+        assert_eq!(record.best_time, Some(Duration::from_secs(3)));
+        assert_eq!(record.best_medal, Some(Medal::Silver));
+        assert_eq!(record.hints_used, 1);
+        assert_eq!(loaded.settings.window_scale, 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_newer_versions() {
+        let dir = temp_dir("newer-version");
+        let save_file = SaveFile {
+            path: Some(dir.join("save.ron")),
+        };
+        let save_data = SaveData {
+            version: SAVE_VERSION + 1,
+            ..default()
+        };
+        save_data.store(&save_file).unwrap();
+        assert!(SaveData::load(&save_file).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn load_migrates_older_versions() {
+        let dir = temp_dir("older-version");
+        let save_file = SaveFile {
+            path: Some(dir.join("save.ron")),
+        };
+        let mut save_data = SaveData {
+            version: 0,
+            ..default()
+        };
+        save_data.complete_level("level", 5, Duration::from_secs(1), None);
+        save_data.store(&save_file).unwrap();
+        let loaded = SaveData::load(&save_file).unwrap();
+        assert_eq!(loaded.version, SAVE_VERSION);
+        assert!(loaded.is_completed("level"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn store_leaves_no_temporary_file_behind() {
+        let dir = temp_dir("no-temp-file");
+        let path = dir.join("save.ron");
+        let save_file = SaveFile {
+            path: Some(path.clone()),
+        };
+        SaveData::default().store(&save_file).unwrap();
+        // storing again replaces the existing save
+        SaveData::default().store(&save_file).unwrap();
+        let files: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files, vec![path]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+use crate::gravity::LevelRules;
+use crate::history::record_turn;
+use crate::pause::{resuming, LeaveLevel};
+use crate::player::{handle_move_player, PullMoveEvent, PushMoveEvent, TurnEvent};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MoveCounter>()
+            .add_systems(OnEnter(GameState::Playing), setup_hud.run_if(not(resuming)))
+            .add_systems(
+                Update,
+                (
+                    reset_move_counter,
+                    count_moves.after(handle_move_player).after(record_turn),
+                    update_hud.after(count_moves),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(LeaveLevel, cleanup_hud);
+    }
+}
+
+/// Moves, pushes and pulls of the current attempt. Part of the undo
+/// history, and reset whenever the level (re)spawns.
+#[derive(Default, Clone, Copy, Debug, Resource)]
+pub struct MoveCounter {
+    pub moves: u32,
+    pub pushes: u32,
+    pub pulls: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Medal {
+    Bronze,
+    Silver,
+    Gold,
+}
+
+impl Medal {
+    /// Gold for matching par, silver for staying within a quarter above it,
+    /// bronze for finishing at all. Without a par there is nothing to rate.
+    pub fn for_moves(moves: u32, par: Option<u32>) -> Option<Medal> {
+        let par = par?;
+        if moves <= par {
+            Some(Medal::Gold)
+        } else if moves * 4 <= par * 5 {
+            Some(Medal::Silver)
+        } else {
+            Some(Medal::Bronze)
+        }
+    }
+}
+
+#[derive(Component)]
+struct Hud;
+
+pub fn reset_move_counter(
+    mut move_counter: ResMut<MoveCounter>,
+    mut level_events: EventReader<LevelEvent>,
+) {
+    for level_event in level_events.iter() {
+        if let LevelEvent::Spawned(_) = level_event {
+            *move_counter = MoveCounter::default();
+        }
+    }
+}
+
+// a turn moving several blocks at once still counts as a single push or pull
+pub fn count_moves(
+    mut turns: EventReader<TurnEvent>,
+    mut ev_push_move: EventReader<PushMoveEvent>,
+    mut ev_pull_move: EventReader<PullMoveEvent>,
+    mut move_counter: ResMut<MoveCounter>,
+) {
+    for _ in turns.iter() {
+        move_counter.moves += 1;
+    }
+    if ev_push_move.iter().count() > 0 {
+        move_counter.pushes += 1;
+    }
+    if ev_pull_move.iter().count() > 0 {
+        move_counter.pulls += 1;
+    }
+}
+
+fn setup_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.),
+            left: Val::Px(10.),
+            ..default()
+        }),
+        Hud,
+    ));
+}
+
+fn update_hud(
+    move_counter: Res<MoveCounter>,
+    level_rules: Res<LevelRules>,
+    mut hud_query: Query<&mut Text, With<Hud>>,
+) {
+    if !move_counter.is_changed() && !level_rules.is_changed() {
+        return;
+    }
+    let par = level_rules
+        .par
+        .map_or(String::new(), |par| format!("  Par: {par}"));
+    for mut text in hud_query.iter_mut() {
+        text.sections[0].value = format!(
+            "Moves: {}  Pushes: {}  Pulls: {}{par}",
+            move_counter.moves, move_counter.pushes, move_counter.pulls
+        );
+    }
+}
+
+fn cleanup_hud(mut commands: Commands, hud_query: Query<Entity, With<Hud>>) {
+    for entity in hud_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn medals_need_a_par() {
+        assert_eq!(Medal::for_moves(3, None), None);
+    }
+
+    #[test]
+    fn gold_for_matching_or_beating_par() {
+        assert_eq!(Medal::for_moves(8, Some(8)), Some(Medal::Gold));
+        assert_eq!(Medal::for_moves(6, Some(8)), Some(Medal::Gold));
+    }
+
+    #[test]
+    fn silver_up_to_a_quarter_above_par() {
+        assert_eq!(Medal::for_moves(9, Some(8)), Some(Medal::Silver));
+        assert_eq!(Medal::for_moves(10, Some(8)), Some(Medal::Silver));
+    }
+
+    #[test]
+    fn bronze_beyond_a_quarter_above_par() {
+        assert_eq!(Medal::for_moves(11, Some(8)), Some(Medal::Bronze));
+        assert_eq!(Medal::for_moves(100, Some(8)), Some(Medal::Bronze));
+    }
+}
@@ -0,0 +1,413 @@
+use crate::menu::ButtonColors;
+use crate::save::{SaveData, SaveFile, Settings};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::window::{PrimaryWindow, WindowMode};
+
+pub struct SettingsPlugin;
+
+// Changes are applied as soon as they are made and written to the save file
+// when the screen is left.
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SettingsReturn>()
+            .init_resource::<SettingsFocus>()
+            .add_systems(OnEnter(GameState::Settings), setup_settings)
+            .add_systems(
+                Update,
+                (
+                    navigate_settings,
+                    click_settings_button,
+                    update_settings_rows
+                        .after(navigate_settings)
+                        .after(click_settings_button),
+                )
+                    .run_if(in_state(GameState::Settings)),
+            )
+            .add_systems(Update, apply_window_settings)
+            .add_systems(
+                OnExit(GameState::Settings),
+                (store_settings, cleanup_settings),
+            );
+    }
+}
+
+const MOVE_DURATION_STEP: i64 = 25;
+const MIN_MOVE_DURATION: u64 = 50;
+const MAX_MOVE_DURATION: u64 = 300;
+const MAX_WINDOW_SCALE: u32 = 4;
+const PLAYFIELD_SIZE: f32 = 256.;
+
+/// The state the settings screen goes back to. Whoever opens the screen from
+/// somewhere other than the main menu sets this first.
+#[derive(Resource)]
+pub struct SettingsReturn(pub GameState);
+
+impl Default for SettingsReturn {
+    fn default() -> Self {
+        SettingsReturn(GameState::Menu)
+    }
+}
+
+#[derive(Default, Resource)]
+struct SettingsFocus(usize);
+
+#[derive(Component)]
+struct SettingsScreen;
+
+#[derive(Clone, Copy)]
+enum SettingsRow {
+    MusicVolume,
+    SfxVolume,
+    MoveDuration,
+    ReducedMotion,
+    Fullscreen,
+    WindowSize,
+    HighlightStyle,
+    MovePreview,
+    Back,
+}
+
+const ROWS: [SettingsRow; 9] = [
+    SettingsRow::MusicVolume,
+    SettingsRow::SfxVolume,
+    SettingsRow::MoveDuration,
+    SettingsRow::ReducedMotion,
+    SettingsRow::Fullscreen,
+    SettingsRow::WindowSize,
+    SettingsRow::HighlightStyle,
+    SettingsRow::MovePreview,
+    SettingsRow::Back,
+];
+
+impl SettingsRow {
+    fn label(&self, settings: &Settings) -> String {
+        let on_off = |on| if on { "On" } else { "Off" };
+        match self {
+            SettingsRow::MusicVolume => {
+                format!("Music: {:.0}%", settings.music_volume * 100.)
+            }
+            SettingsRow::SfxVolume => {
+                format!("Sound effects: {:.0}%", settings.sfx_volume * 100.)
+            }
+            SettingsRow::MoveDuration => {
+                format!("Move duration: {} ms", settings.move_duration_millis)
+            }
+            SettingsRow::ReducedMotion => {
+                format!("Reduced motion: {}", on_off(settings.reduced_motion))
+            }
+            SettingsRow::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+            SettingsRow::WindowSize => {
+                let size = PLAYFIELD_SIZE as u32 * settings.window_scale;
+                format!("Window size: {size}x{size}")
+            }
+            SettingsRow::HighlightStyle => {
+                format!("Grab highlight: {}", settings.highlight_style.name())
+            }
+            SettingsRow::MovePreview => format!("Move preview: {}", on_off(settings.move_preview)),
+            SettingsRow::Back => "Back".to_string(),
+        }
+    }
+
+    /// Steps the value up or down; toggles flip either way.
+    fn adjust(&self, settings: &mut Settings, step: i32) {
+        let step_volume = |volume: f64| ((volume * 10.).round() + step as f64).clamp(0., 10.) / 10.;
+        match self {
+            SettingsRow::MusicVolume => settings.music_volume = step_volume(settings.music_volume),
+            SettingsRow::SfxVolume => settings.sfx_volume = step_volume(settings.sfx_volume),
+            SettingsRow::MoveDuration => {
+                settings.move_duration_millis = settings
+                    .move_duration_millis
+                    .saturating_add_signed(step as i64 * MOVE_DURATION_STEP)
+                    .clamp(MIN_MOVE_DURATION, MAX_MOVE_DURATION);
+            }
+            SettingsRow::ReducedMotion => settings.reduced_motion = !settings.reduced_motion,
+            SettingsRow::Fullscreen => settings.fullscreen = !settings.fullscreen,
+            SettingsRow::WindowSize => {
+                settings.window_scale = settings
+                    .window_scale
+                    .saturating_add_signed(step)
+                    .clamp(1, MAX_WINDOW_SCALE);
+            }
+            SettingsRow::HighlightStyle => {
+                settings.highlight_style = settings.highlight_style.cycle(step)
+            }
+            SettingsRow::MovePreview => settings.move_preview = !settings.move_preview,
+            SettingsRow::Back => {}
+        }
+    }
+}
+
+#[derive(Component)]
+struct SettingsLabel(usize);
+
+#[derive(Component)]
+struct SettingsButton {
+    index: usize,
+    step: i32,
+}
+
+fn setup_settings(mut commands: Commands, mut focus: ResMut<SettingsFocus>) {
+    focus.0 = 0;
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            SettingsScreen,
+        ))
+        .with_children(|children| {
+            children.spawn(TextBundle::from_section(
+                "Settings",
+                TextStyle {
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+            for (index, row) in ROWS.iter().enumerate() {
+                children
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            margin: UiRect::all(Val::Px(3.)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| match row {
+                        SettingsRow::Back => {
+                            spawn_settings_button(parent, index, 0, 300.0);
+                        }
+                        _ => {
+                            spawn_settings_button(parent, index, -1, 40.0);
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    "",
+                                    TextStyle {
+                                        font_size: 24.0,
+                                        color: Color::rgb(0.9, 0.9, 0.9),
+                                        ..default()
+                                    },
+                                )
+                                .with_style(Style {
+                                    width: Val::Px(300.0),
+                                    margin: UiRect::horizontal(Val::Px(10.)),
+                                    ..default()
+                                }),
+                                SettingsLabel(index),
+                            ));
+                            spawn_settings_button(parent, index, 1, 40.0);
+                        }
+                    });
+            }
+        });
+}
+
+fn spawn_settings_button(parent: &mut ChildBuilder, index: usize, step: i32, width: f32) {
+    let button_colors = ButtonColors::default();
+    let label = match step {
+        -1 => "-",
+        1 => "+",
+        _ => "Back",
+    };
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(width),
+                    height: Val::Px(40.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: button_colors.normal.into(),
+                ..default()
+            },
+            button_colors,
+            SettingsButton { index, step },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font_size: 24.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn activate_row(
+    index: usize,
+    step: i32,
+    save_data: &mut SaveData,
+    settings_return: &SettingsReturn,
+    next_state: &mut NextState<GameState>,
+) {
+    match ROWS[index] {
+        SettingsRow::Back => next_state.set(settings_return.0.clone()),
+        row => row.adjust(&mut save_data.settings, step),
+    }
+}
+
+fn navigate_settings(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut focus: ResMut<SettingsFocus>,
+    mut save_data: ResMut<SaveData>,
+    settings_return: Res<SettingsReturn>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let gamepad_pressed = |button_type| {
+        gamepads
+            .iter()
+            .any(|gamepad| gamepad_input.just_pressed(GamepadButton::new(gamepad, button_type)))
+    };
+
+    if keyboard_input.any_just_pressed([KeyCode::W, KeyCode::Up])
+        || gamepad_pressed(GamepadButtonType::DPadUp)
+    {
+        focus.0 = focus.0.saturating_sub(1);
+    } else if keyboard_input.any_just_pressed([KeyCode::S, KeyCode::Down])
+        || gamepad_pressed(GamepadButtonType::DPadDown)
+    {
+        focus.0 = (focus.0 + 1).min(ROWS.len() - 1);
+    } else if keyboard_input.any_just_pressed([KeyCode::A, KeyCode::Left])
+        || gamepad_pressed(GamepadButtonType::DPadLeft)
+    {
+        ROWS[focus.0].adjust(&mut save_data.settings, -1);
+    } else if keyboard_input.any_just_pressed([KeyCode::D, KeyCode::Right])
+        || gamepad_pressed(GamepadButtonType::DPadRight)
+    {
+        ROWS[focus.0].adjust(&mut save_data.settings, 1);
+    } else if keyboard_input.any_just_pressed([KeyCode::Return, KeyCode::Space])
+        || gamepad_pressed(GamepadButtonType::South)
+    {
+        activate_row(
+            focus.0,
+            1,
+            &mut save_data,
+            &settings_return,
+            &mut next_state,
+        );
+    } else if keyboard_input.just_pressed(KeyCode::Escape)
+        || gamepad_pressed(GamepadButtonType::East)
+    {
+        next_state.set(settings_return.0.clone());
+    }
+}
+
+// mouse and touch both go through ui interactions
+fn click_settings_button(
+    interaction_query: Query<(&Interaction, &SettingsButton), Changed<Interaction>>,
+    mut focus: ResMut<SettingsFocus>,
+    mut save_data: ResMut<SaveData>,
+    settings_return: Res<SettingsReturn>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        match *interaction {
+            Interaction::Pressed => {
+                focus.0 = button.index;
+                activate_row(
+                    button.index,
+                    button.step,
+                    &mut save_data,
+                    &settings_return,
+                    &mut next_state,
+                );
+            }
+            Interaction::Hovered => focus.0 = button.index,
+            Interaction::None => {}
+        }
+    }
+}
+
+fn update_settings_rows(
+    focus: Res<SettingsFocus>,
+    save_data: Res<SaveData>,
+    mut label_query: Query<(&SettingsLabel, &mut Text)>,
+    mut button_query: Query<(&SettingsButton, &ButtonColors, &mut BackgroundColor)>,
+) {
+    for (label, mut text) in label_query.iter_mut() {
+        let value = ROWS[label.0].label(&save_data.settings);
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+    for (button, button_colors, mut color) in button_query.iter_mut() {
+        let new_color = if button.index == focus.0 {
+            button_colors.hovered
+        } else {
+            button_colors.normal
+        };
+        if color.0 != new_color {
+            color.0 = new_color;
+        }
+    }
+}
+
+fn apply_window_settings(
+    save_data: Res<SaveData>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+    mut applied: Local<Option<(bool, u32)>>,
+) {
+    if !save_data.is_changed() {
+        return;
+    }
+    // the save data also changes when levels are completed, which leaves the window alone
+    let window_settings = (
+        save_data.settings.fullscreen,
+        save_data.settings.window_scale,
+    );
+    if *applied == Some(window_settings) {
+        return;
+    }
+    let Ok(mut window) = window_query.get_single_mut() else {
+        return;
+    };
+    *applied = Some(window_settings);
+    window.mode = if save_data.settings.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    };
+    // the canvas on the web is sized by the page
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let size = PLAYFIELD_SIZE * save_data.settings.window_scale as f32;
+        window.resolution.set(size, size);
+    }
+}
+
+fn store_settings(
+    save_data: Res<SaveData>,
+    save_file: Res<SaveFile>,
+    mut settings_return: ResMut<SettingsReturn>,
+) {
+    *settings_return = SettingsReturn::default();
+    if let Err(error) = save_data.store(&save_file) {
+        warn!("Failed to write save file {:?}: {error}", save_file.path);
+    }
+}
+
+fn cleanup_settings(mut commands: Commands, query: Query<Entity, With<SettingsScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,682 @@
+use crate::player::{get_movement_coords_from_direction, Direction};
+use crate::tile_map::{LevelWalls, Tile};
+use bevy_ecs_ldtk::GridCoords;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// A model of the grid rules that can be searched without touching the ECS.
+// It covers moving, grabbing, push chains, crumbling floors, fragile blocks
+// and spikes. Gravity, enemies and lasers are not modelled, levels using
+// them can't be solved here.
+
+/// Search is abandoned after visiting this many states.
+pub const MAX_STATES: usize = 200_000;
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::North,
+    Direction::East,
+    Direction::South,
+    Direction::West,
+];
+
+/// The parts of a level that don't change while it is played.
+pub struct Puzzle {
+    pub walls: LevelWalls,
+    pub goals: Vec<GridCoords>,
+    pub spikes: HashSet<GridCoords>,
+    pub push_limit: Option<u32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PlayerState {
+    pub grid_coords: GridCoords,
+    /// Index into the four directions, `Direction` itself isn't hashable.
+    face: u8,
+    pub grabbing: bool,
+}
+
+impl PlayerState {
+    pub fn new(grid_coords: GridCoords, face: Direction, grabbing: bool) -> Self {
+        PlayerState {
+            grid_coords,
+            face: direction_index(face),
+            grabbing,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BlockState {
+    pub grid_coords: GridCoords,
+    pub grabbed: bool,
+    /// Pushes left before a fragile block breaks.
+    pub pushes_left: Option<i32>,
+}
+
+impl BlockState {
+    fn broken(&self) -> bool {
+        self.pushes_left
+            .map_or(false, |pushes_left| pushes_left <= 0)
+    }
+}
+
+/// Everything a turn can change, in a fixed entity order.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PuzzleState {
+    pub players: Vec<PlayerState>,
+    pub blocks: Vec<BlockState>,
+    /// Direction of the current grab, if any.
+    grab: Option<u8>,
+    /// Crumbling floors that turned into pits during the search, sorted.
+    crumbled: Vec<GridCoords>,
+}
+
+impl PuzzleState {
+    pub fn new(
+        players: Vec<PlayerState>,
+        blocks: Vec<BlockState>,
+        grab_direction: Option<Direction>,
+    ) -> Self {
+        PuzzleState {
+            players,
+            blocks,
+            grab: grab_direction.map(direction_index),
+            crumbled: Vec::new(),
+        }
+    }
+}
+
+/// One input. Grabbing, letting go and turning on the spot don't count as moves.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Step {
+    Move(Direction),
+    Grab,
+    Release,
+}
+
+/// Why a state can't be completed any more, found without searching.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Deadlock {
+    /// A block sits where no sequence of pushes and pulls brings it to a goal,
+    /// and there aren't enough other players and blocks left for every goal.
+    StuckBlock(GridCoords),
+    /// Blocks wedged against walls and each other so none of them can move
+    /// again, leaving too few movables for the goals.
+    FrozenBlocks,
+    /// No player can get onto or next to this goal any more.
+    UnreachableGoal(GridCoords),
+}
+
+#[derive(Debug)]
+pub enum Solution {
+    /// The fewest moves to completion, empty if the level is already complete.
+    Solved(Vec<Step>),
+    Unsolvable,
+    /// Gave up after `MAX_STATES` states.
+    TooHard,
+}
+
+fn direction_index(direction: Direction) -> u8 {
+    DIRECTIONS
+        .iter()
+        .position(|candidate| *candidate == direction)
+        .unwrap_or(1) as u8
+}
+
+fn offset(direction_index: u8) -> GridCoords {
+    get_movement_coords_from_direction(DIRECTIONS[direction_index as usize])
+        .unwrap_or(GridCoords::new(0, 0))
+}
+
+fn on_axis(a: u8, b: u8) -> bool {
+    a % 2 == b % 2
+}
+
+enum Occupant {
+    Player,
+    Block(usize),
+}
+
+impl Puzzle {
+    fn in_wall(&self, state: &PuzzleState, grid_coords: &GridCoords) -> bool {
+        self.walls.in_wall(grid_coords) || state.crumbled.binary_search(grid_coords).is_ok()
+    }
+
+    fn occupant(&self, state: &PuzzleState, grid_coords: &GridCoords) -> Option<Occupant> {
+        if state
+            .players
+            .iter()
+            .any(|player| player.grid_coords == *grid_coords)
+        {
+            return Some(Occupant::Player);
+        }
+        state
+            .blocks
+            .iter()
+            .position(|block| !block.broken() && block.grid_coords == *grid_coords)
+            .map(Occupant::Block)
+    }
+
+    // mirrors `can_move` in the player module
+    fn can_move(
+        &self,
+        state: &PuzzleState,
+        from: GridCoords,
+        direction: u8,
+        push_limit: Option<u32>,
+    ) -> bool {
+        let next = from + offset(direction);
+        let Some(occupant) = self.occupant(state, &next) else {
+            return true;
+        };
+        if let Occupant::Block(index) = occupant {
+            if !state.blocks[index].grabbed {
+                return false;
+            }
+        }
+        if push_limit == Some(0) {
+            return false;
+        }
+        if self.in_wall(state, &(next + offset(direction))) {
+            return false;
+        }
+        self.can_move(
+            state,
+            next,
+            direction,
+            push_limit.map(|push_limit| push_limit - 1),
+        )
+    }
+
+    fn mover_can_step(&self, state: &PuzzleState, from: GridCoords, direction: u8) -> bool {
+        if let Some(grab) = state.grab {
+            if !on_axis(grab, direction) {
+                return false;
+            }
+        }
+        !self.in_wall(state, &(from + offset(direction)))
+            && self.can_move(state, from, direction, self.push_limit)
+    }
+
+    pub fn is_complete(&self, state: &PuzzleState) -> bool {
+        !self.goals.is_empty()
+            && self.goals.iter().all(|goal| {
+                state
+                    .players
+                    .iter()
+                    .any(|player| player.grid_coords == *goal)
+                    || state
+                        .blocks
+                        .iter()
+                        .any(|block| !block.broken() && block.grid_coords == *goal)
+            })
+    }
+
+    pub fn is_dead(&self, state: &PuzzleState) -> bool {
+        state
+            .players
+            .iter()
+            .any(|player| self.spikes.contains(&player.grid_coords))
+    }
+
+    /// The state after `step` and whether it took a move, or `None` if the
+    /// step changes nothing or kills a player.
+    pub fn apply(&self, state: &PuzzleState, step: Step) -> Option<(PuzzleState, bool)> {
+        let mut next = state.clone();
+        let moved = match step {
+            Step::Grab => {
+                if state.grab.is_some() {
+                    return None;
+                }
+                for block in next.blocks.iter_mut().filter(|block| !block.broken()) {
+                    for player in next.players.iter_mut() {
+                        if player.grid_coords + offset(player.face) == block.grid_coords {
+                            block.grabbed = true;
+                            player.grabbing = true;
+                            next.grab = Some(player.face);
+                        }
+                    }
+                }
+                if next.grab.is_none() {
+                    return None;
+                }
+                false
+            }
+            Step::Release => {
+                if state.grab.is_none() {
+                    return None;
+                }
+                self.release(&mut next);
+                false
+            }
+            Step::Move(direction) => {
+                let direction = direction_index(direction);
+                if state.grab.is_none() {
+                    for player in next.players.iter_mut() {
+                        player.face = direction;
+                    }
+                }
+                // everyone decides on the state before the turn, then moves together
+                let moving_players: Vec<usize> = (0..state.players.len())
+                    .filter(|index| {
+                        self.mover_can_step(state, state.players[*index].grid_coords, direction)
+                    })
+                    .collect();
+                let moving_blocks: Vec<usize> = (0..state.blocks.len())
+                    .filter(|index| {
+                        let block = &state.blocks[*index];
+                        block.grabbed
+                            && !block.broken()
+                            && self.mover_can_step(state, block.grid_coords, direction)
+                    })
+                    .collect();
+                let mut origins = Vec::new();
+                for index in moving_players.iter() {
+                    let player = &mut next.players[*index];
+                    origins.push(player.grid_coords);
+                    player.grid_coords = player.grid_coords + offset(direction);
+                }
+                let mut broke = false;
+                for index in moving_blocks.iter() {
+                    let block = &mut next.blocks[*index];
+                    origins.push(block.grid_coords);
+                    block.grid_coords = block.grid_coords + offset(direction);
+                    if state.grab == Some(direction) {
+                        if let Some(pushes_left) = block.pushes_left.as_mut() {
+                            *pushes_left -= 1;
+                            if *pushes_left <= 0 {
+                                block.grabbed = false;
+                                broke = true;
+                            }
+                        }
+                    }
+                }
+                if broke {
+                    self.release(&mut next);
+                }
+                for origin in origins {
+                    if self.walls.tile(&origin) == Some(Tile::CrumblingFloor)
+                        && self.occupant(&next, &origin).is_none()
+                    {
+                        if let Err(index) = next.crumbled.binary_search(&origin) {
+                            next.crumbled.insert(index, origin);
+                        }
+                    }
+                }
+                !moving_players.is_empty() || !moving_blocks.is_empty()
+            }
+        };
+        if next == *state || self.is_dead(&next) {
+            return None;
+        }
+        Some((next, moved))
+    }
+
+    fn release(&self, state: &mut PuzzleState) {
+        state.grab = None;
+        for player in state.players.iter_mut() {
+            player.grabbing = false;
+        }
+        for block in state.blocks.iter_mut() {
+            block.grabbed = false;
+        }
+    }
+
+    /// Finds the fewest moves to complete the level from `start`. Steps that
+    /// don't count as moves are free, so this is a breadth first search that
+    /// puts them at the front of the queue.
+    pub fn solve(&self, start: &PuzzleState) -> Solution {
+        if self.is_dead(start) {
+            return Solution::Unsolvable;
+        }
+        let steps: Vec<Step> = DIRECTIONS
+            .iter()
+            .map(|direction| Step::Move(*direction))
+            .chain([Step::Grab, Step::Release])
+            .collect();
+        // per state: how it was reached and in how many moves
+        let mut nodes: Vec<(PuzzleState, Option<(usize, Step)>, u32)> =
+            vec![(start.clone(), None, 0)];
+        let mut index_of: HashMap<PuzzleState, usize> = HashMap::from([(start.clone(), 0)]);
+        let mut queue = VecDeque::from([(0, 0)]);
+
+        while let Some((index, moves)) = queue.pop_front() {
+            if moves > nodes[index].2 {
+                continue;
+            }
+            if self.is_complete(&nodes[index].0) {
+                let mut path = Vec::new();
+                let mut current = index;
+                while let Some((parent, step)) = nodes[current].1 {
+                    path.push(step);
+                    current = parent;
+                }
+                path.reverse();
+                return Solution::Solved(path);
+            }
+            if nodes.len() >= MAX_STATES {
+                return Solution::TooHard;
+            }
+            for step in steps.iter() {
+                let Some((next, moved)) = self.apply(&nodes[index].0, *step) else {
+                    continue;
+                };
+                let next_moves = moves + moved as u32;
+                match index_of.get(&next) {
+                    Some(&known) if nodes[known].2 <= next_moves => continue,
+                    Some(&known) => {
+                        nodes[known].1 = Some((index, *step));
+                        nodes[known].2 = next_moves;
+                        if moved {
+                            queue.push_back((known, next_moves));
+                        } else {
+                            queue.push_front((known, next_moves));
+                        }
+                    }
+                    None => {
+                        let known = nodes.len();
+                        index_of.insert(next.clone(), known);
+                        nodes.push((next, Some((index, *step)), next_moves));
+                        if moved {
+                            queue.push_back((known, next_moves));
+                        } else {
+                            queue.push_front((known, next_moves));
+                        }
+                    }
+                }
+            }
+        }
+        Solution::Unsolvable
+    }
+
+    /// Cells a block can be moved onto a goal from, on an otherwise empty level.
+    /// Moving a block needs a free cell behind it to push from, or two in
+    /// front of it to pull into.
+    fn live_cells(&self, state: &PuzzleState) -> HashSet<GridCoords> {
+        let free = |grid_coords: &GridCoords| !self.in_wall(state, grid_coords);
+        let mut live: HashSet<GridCoords> = self.goals.iter().copied().collect();
+        let mut queue: VecDeque<GridCoords> = self.goals.iter().copied().collect();
+        while let Some(to) = queue.pop_front() {
+            for direction in 0..DIRECTIONS.len() as u8 {
+                let step = offset(direction);
+                let from = GridCoords::new(to.x - step.x, to.y - step.y);
+                let pushed = free(&GridCoords::new(from.x - step.x, from.y - step.y));
+                let pulled = free(&(to + step));
+                if free(&from) && free(&to) && (pushed || pulled) && live.insert(from) {
+                    queue.push_back(from);
+                }
+            }
+        }
+        live
+    }
+
+    /// Blocks that can never move again, treating the other frozen blocks as
+    /// walls. Starts with every block frozen and thaws them until nothing changes.
+    fn frozen_blocks(&self, state: &PuzzleState) -> Vec<usize> {
+        let mut frozen: Vec<usize> = (0..state.blocks.len())
+            .filter(|index| !state.blocks[*index].broken())
+            .collect();
+        loop {
+            let blocked = |grid_coords: GridCoords, frozen: &[usize]| {
+                self.in_wall(state, &grid_coords)
+                    || frozen
+                        .iter()
+                        .any(|index| state.blocks[*index].grid_coords == grid_coords)
+            };
+            let thawed = frozen.iter().position(|index| {
+                let at = state.blocks[*index].grid_coords;
+                // one axis at a time: north/south, then east/west
+                [0u8, 1].iter().any(|direction| {
+                    let step = offset(*direction);
+                    let back = GridCoords::new(at.x - step.x, at.y - step.y);
+                    let front = at + step;
+                    let free = |grid_coords| !blocked(grid_coords, &frozen);
+                    (free(back) && free(front))
+                        || (free(back) && free(GridCoords::new(back.x - step.x, back.y - step.y)))
+                        || (free(front) && free(front + step))
+                })
+            });
+            match thawed {
+                Some(position) => {
+                    frozen.remove(position);
+                }
+                None => return frozen,
+            }
+        }
+    }
+
+    /// Cheap checks for states that can no longer be completed. Finding
+    /// nothing doesn't mean the state is solvable, only `solve` can tell.
+    pub fn deadlock(&self, state: &PuzzleState) -> Option<Deadlock> {
+        let frozen = self.frozen_blocks(state);
+        let frozen_at: HashSet<GridCoords> = frozen
+            .iter()
+            .map(|index| state.blocks[*index].grid_coords)
+            .collect();
+        let open_goals: Vec<GridCoords> = self
+            .goals
+            .iter()
+            .filter(|goal| !frozen_at.contains(goal))
+            .copied()
+            .collect();
+
+        // players walk anywhere that isn't a wall or a frozen block
+        let mut reachable: HashSet<GridCoords> = state
+            .players
+            .iter()
+            .map(|player| player.grid_coords)
+            .collect();
+        let mut queue: VecDeque<GridCoords> = reachable.iter().copied().collect();
+        while let Some(at) = queue.pop_front() {
+            for direction in 0..DIRECTIONS.len() as u8 {
+                let next = at + offset(direction);
+                if !self.in_wall(state, &next)
+                    && !frozen_at.contains(&next)
+                    && reachable.insert(next)
+                {
+                    queue.push_back(next);
+                }
+            }
+        }
+        if let Some(goal) = open_goals.iter().find(|goal| {
+            !reachable.contains(goal)
+                && (0..DIRECTIONS.len() as u8)
+                    .all(|direction| !reachable.contains(&(**goal + offset(direction))))
+        }) {
+            return Some(Deadlock::UnreachableGoal(*goal));
+        }
+
+        let live = self.live_cells(state);
+        let stuck = state.blocks.iter().enumerate().find(|(index, block)| {
+            !block.broken() && !frozen.contains(index) && !live.contains(&block.grid_coords)
+        });
+        let usable = state.players.len()
+            + state
+                .blocks
+                .iter()
+                .enumerate()
+                .filter(|(index, block)| {
+                    !block.broken() && !frozen.contains(index) && live.contains(&block.grid_coords)
+                })
+                .count();
+        if usable >= open_goals.len() {
+            return None;
+        }
+        match stuck {
+            Some((_, block)) => Some(Deadlock::StuckBlock(block.grid_coords)),
+            None => Some(Deadlock::FrozenBlocks),
+        }
+    }
+}
+
+/// How many turns to undo to get back to a state that isn't known to be lost,
+/// given the level before each turn, latest first. States the search gives up
+/// on might still be winnable, so those count. None if only a restart helps.
+pub fn undos_to_solvable(earlier: Vec<(Puzzle, PuzzleState)>) -> Option<usize> {
+    earlier
+        .into_iter()
+        .position(|(puzzle, state)| !matches!(puzzle.solve(&state), Solution::Unsolvable))
+        .map(|undos| undos + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corridor(width: i32) -> LevelWalls {
+        LevelWalls::new(GridCoords::new(0, 0), width, 1)
+    }
+
+    fn puzzle(walls: LevelWalls, goals: Vec<GridCoords>) -> Puzzle {
+        Puzzle {
+            walls,
+            goals,
+            spikes: HashSet::new(),
+            push_limit: None,
+        }
+    }
+
+    fn player(x: i32, y: i32) -> PlayerState {
+        PlayerState::new(GridCoords::new(x, y), Direction::East, false)
+    }
+
+    fn block(x: i32, y: i32, pushes_left: Option<i32>) -> BlockState {
+        BlockState {
+            grid_coords: GridCoords::new(x, y),
+            grabbed: false,
+            pushes_left,
+        }
+    }
+
+    #[test]
+    fn solve_finds_the_fewest_moves() {
+        let puzzle = puzzle(corridor(4), vec![GridCoords::new(3, 0)]);
+        let state = PuzzleState::new(vec![player(0, 0)], Vec::new(), None);
+        let Solution::Solved(steps) = puzzle.solve(&state) else {
+            panic!("the corridor should be solvable");
+        };
+        assert_eq!(steps, vec![Step::Move(Direction::East); 3]);
+    }
+
+    #[test]
+    fn solve_gives_up_on_walled_off_goals() {
+        let mut walls = corridor(4);
+        walls.set_tile(&GridCoords::new(2, 0), Tile::Wall);
+        let puzzle = puzzle(walls, vec![GridCoords::new(3, 0)]);
+        let state = PuzzleState::new(vec![player(0, 0)], Vec::new(), None);
+        assert!(matches!(puzzle.solve(&state), Solution::Unsolvable));
+    }
+
+    #[test]
+    fn apply_pushes_a_grabbed_block() {
+        let puzzle = puzzle(corridor(4), Vec::new());
+        let state = PuzzleState::new(vec![player(0, 0)], vec![block(1, 0, None)], None);
+        let (grabbed, moved) = puzzle.apply(&state, Step::Grab).unwrap();
+        assert!(!moved);
+        assert!(grabbed.blocks[0].grabbed);
+        let (pushed, moved) = puzzle.apply(&grabbed, Step::Move(Direction::East)).unwrap();
+        assert!(moved);
+        assert_eq!(pushed.players[0].grid_coords, GridCoords::new(1, 0));
+        assert_eq!(pushed.blocks[0].grid_coords, GridCoords::new(2, 0));
+    }
+
+    #[test]
+    fn apply_pulls_a_grabbed_block_without_wearing_it() {
+        let puzzle = puzzle(corridor(4), Vec::new());
+        let state = PuzzleState::new(vec![player(1, 0)], vec![block(2, 0, Some(1))], None);
+        let (grabbed, _) = puzzle.apply(&state, Step::Grab).unwrap();
+        let (pulled, moved) = puzzle.apply(&grabbed, Step::Move(Direction::West)).unwrap();
+        assert!(moved);
+        assert_eq!(pulled.players[0].grid_coords, GridCoords::new(0, 0));
+        assert_eq!(pulled.blocks[0].grid_coords, GridCoords::new(1, 0));
+        assert_eq!(pulled.blocks[0].pushes_left, Some(1));
+    }
+
+    #[test]
+    fn apply_breaks_fragile_blocks_and_lets_go() {
+        let puzzle = puzzle(corridor(4), Vec::new());
+        let state = PuzzleState::new(vec![player(0, 0)], vec![block(1, 0, Some(1))], None);
+        let (grabbed, _) = puzzle.apply(&state, Step::Grab).unwrap();
+        let (pushed, _) = puzzle.apply(&grabbed, Step::Move(Direction::East)).unwrap();
+        assert!(pushed.blocks[0].broken());
+        assert_eq!(pushed.grab, None);
+        assert!(!pushed.players[0].grabbing);
+        assert!(puzzle.occupant(&pushed, &GridCoords::new(2, 0)).is_none());
+    }
+
+    #[test]
+    fn apply_crumbles_floors_that_are_left() {
+        let mut walls = corridor(3);
+        walls.set_tile(&GridCoords::new(0, 0), Tile::CrumblingFloor);
+        let puzzle = puzzle(walls, Vec::new());
+        let state = PuzzleState::new(vec![player(0, 0)], Vec::new(), None);
+        let (moved_on, _) = puzzle.apply(&state, Step::Move(Direction::East)).unwrap();
+        assert_eq!(moved_on.crumbled, vec![GridCoords::new(0, 0)]);
+        // there is no way back, turning around is all that's left
+        let (turned, moved) = puzzle
+            .apply(&moved_on, Step::Move(Direction::West))
+            .unwrap();
+        assert!(!moved);
+        assert_eq!(turned.players[0].grid_coords, GridCoords::new(1, 0));
+    }
+
+    #[test]
+    fn deadlock_finds_unreachable_goals() {
+        let mut walls = corridor(5);
+        walls.set_tile(&GridCoords::new(2, 0), Tile::Wall);
+        let puzzle = puzzle(walls, vec![GridCoords::new(4, 0)]);
+        let state = PuzzleState::new(vec![player(0, 0)], Vec::new(), None);
+        assert_eq!(
+            puzzle.deadlock(&state),
+            Some(Deadlock::UnreachableGoal(GridCoords::new(4, 0)))
+        );
+    }
+
+    #[test]
+    fn deadlock_finds_frozen_blocks() {
+        let mut walls = LevelWalls::new(GridCoords::new(0, 0), 4, 3);
+        walls.set_tile(&GridCoords::new(1, 0), Tile::Wall);
+        walls.set_tile(&GridCoords::new(0, 1), Tile::Wall);
+        let puzzle = puzzle(walls, vec![GridCoords::new(3, 2), GridCoords::new(3, 0)]);
+        let state = PuzzleState::new(vec![player(2, 1)], vec![block(0, 0, None)], None);
+        assert_eq!(puzzle.deadlock(&state), Some(Deadlock::FrozenBlocks));
+    }
+
+    #[test]
+    fn deadlock_accepts_open_levels() {
+        let walls = LevelWalls::new(GridCoords::new(0, 0), 4, 3);
+        let puzzle = puzzle(walls, vec![GridCoords::new(3, 2), GridCoords::new(3, 0)]);
+        let state = PuzzleState::new(vec![player(0, 0)], vec![block(1, 1, None)], None);
+        assert_eq!(puzzle.deadlock(&state), None);
+    }
+
+    #[test]
+    fn undos_to_solvable_looks_past_what_deadlock_misses() {
+        // the block needs three pushes to reach the far goal and breaks on
+        // the third once pulling it back has wasted one of them
+        let goals = vec![GridCoords::new(3, 0), GridCoords::new(4, 0)];
+        let start = PuzzleState::new(vec![player(0, 0)], vec![block(1, 0, Some(4))], None);
+        let pushed = PuzzleState::new(vec![player(1, 0)], vec![block(2, 0, Some(3))], None);
+        let pulled_back = PuzzleState::new(vec![player(0, 0)], vec![block(1, 0, Some(3))], None);
+        let lost = puzzle(corridor(5), goals.clone());
+        assert_eq!(lost.deadlock(&pulled_back), None);
+        assert!(matches!(lost.solve(&pulled_back), Solution::Unsolvable));
+
+        let earlier = vec![
+            (lost, pulled_back),
+            (puzzle(corridor(5), goals.clone()), pushed),
+            (puzzle(corridor(5), goals), start),
+        ];
+        assert_eq!(undos_to_solvable(earlier), Some(2));
+    }
+
+    #[test]
+    fn undos_to_solvable_restarts_when_nothing_is() {
+        let mut walls = corridor(4);
+        walls.set_tile(&GridCoords::new(2, 0), Tile::Wall);
+        let earlier = vec![(
+            puzzle(walls, vec![GridCoords::new(3, 0)]),
+            PuzzleState::new(vec![player(0, 0)], Vec::new(), None),
+        )];
+        assert_eq!(undos_to_solvable(earlier), None);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::gravity::apply_gravity;
+use crate::history::{RestartEvent, UndoEvent};
+use crate::occupancy::{update_occupancy, Occupancy};
+use crate::player::{
+    handle_move_player, GlobalPlayerState, Grabbed, Grabbing, Movable, PushMoveEvent,
+};
+use crate::tile_map::{Block, EntityWalls, Pullable, Pushable, Tile, WorldWalls, GRID_SIZE};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_ldtk_int_cell_for_layer::<CrumblingFloorBundle>("Walls", 3)
+            .register_ldtk_entity::<FragileBlockBundle>("FragileBlock")
+            .add_systems(
+                Update,
+                (
+                    setup_crumbling_floors,
+                    crumble_floor.after(update_occupancy).after(apply_gravity),
+                    update_crumbled_floors.after(crumble_floor),
+                    wear_fragile_blocks.after(handle_move_player),
+                    break_fragile_blocks.after(wear_fragile_blocks),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+#[derive(Default, Component)]
+pub struct CrumblingFloor;
+
+#[derive(Default, Bundle, LdtkIntCell)]
+pub struct CrumblingFloorBundle {
+    crumbling_floor: CrumblingFloor,
+}
+
+/// Remaining pushes before the block breaks, read from the LDtk `pushes` field.
+#[derive(Default, Clone, Component)]
+pub struct Fragile {
+    pushes_left: i32,
+}
+
+impl Fragile {
+    pub fn pushes_left(&self) -> i32 {
+        self.pushes_left
+    }
+}
+
+impl From<&EntityInstance> for Fragile {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Fragile {
+            pushes_left: *entity_instance.get_int_field("pushes").unwrap_or(&1),
+        }
+    }
+}
+
+#[derive(Default, Component)]
+pub struct Broken;
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct FragileBlockBundle {
+    block: Block,
+    movable: Movable,
+    pushable: Pushable,
+    pullable: Pullable,
+    #[from_entity_instance]
+    fragile: Fragile,
+    #[sprite_sheet_bundle]
+    sprite_bundle: SpriteSheetBundle,
+    #[grid_coords]
+    grid_coords: GridCoords,
+}
+
+// int cells don't come with a sprite, so give crumbling floors a hidden
+// one that is shown once they turned into a pit
+pub fn setup_crumbling_floors(
+    mut commands: Commands,
+    floor_query: Query<Entity, Added<CrumblingFloor>>,
+) {
+    for entity in floor_query.iter() {
+        commands.entity(entity).insert((
+            Sprite {
+                color: Color::BLACK,
+                custom_size: Some(Vec2::splat(GRID_SIZE as f32)),
+                ..default()
+            },
+            Handle::<Image>::default(),
+            Visibility::Hidden,
+            ComputedVisibility::default(),
+        ));
+    }
+}
+
+// a floor gives way once whatever stood on it steps, gets pulled or falls off,
+// while undo and restart only put things back where they were
+pub fn crumble_floor(
+    mut occupancy: ResMut<Occupancy>,
+    mut ev_undo: EventReader<UndoEvent>,
+    mut ev_restart: EventReader<RestartEvent>,
+    mut world_walls: ResMut<WorldWalls>,
+) {
+    let undone = ev_undo.iter().count() + ev_restart.iter().count() > 0;
+    if occupancy.vacated().is_empty() {
+        return;
+    }
+    let vacated = occupancy.take_vacated();
+    if undone {
+        return;
+    }
+    for world_coords in vacated {
+        // a pulled block takes the cell its player leaves, the floor holds until it moves on
+        if occupancy.get(world_coords).is_some() {
+            continue;
+        }
+        if world_walls.tile_at(world_coords) == Some(Tile::CrumblingFloor) {
+            world_walls.set_tile_at(world_coords, Tile::Pit);
+        }
+    }
+}
+
+pub fn update_crumbled_floors(
+    walls: EntityWalls,
+    mut floor_query: Query<(Entity, &GridCoords, &mut Visibility), With<CrumblingFloor>>,
+) {
+    if !walls.is_changed() {
+        return;
+    }
+    for (entity, grid_coords, mut visibility) in floor_query.iter_mut() {
+        let new_visibility = if walls.of(entity).tile(grid_coords) == Some(Tile::Pit) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != new_visibility {
+            *visibility = new_visibility;
+        }
+    }
+}
+
+pub fn wear_fragile_blocks(
+    mut ev_push_move: EventReader<PushMoveEvent>,
+    mut fragile_query: Query<&mut Fragile>,
+) {
+    for PushMoveEvent(entity, _) in ev_push_move.iter() {
+        if let Ok(mut fragile) = fragile_query.get_mut(*entity) {
+            fragile.pushes_left -= 1;
+        }
+    }
+}
+
+// breaking only takes the block out of play, so undo can put it back
+pub fn break_fragile_blocks(
+    mut commands: Commands,
+    fragile_query: Query<(Entity, &Fragile, Option<&Broken>), Changed<Fragile>>,
+    grabbing_query: Query<Entity, With<Grabbing>>,
+    mut global_player_state: ResMut<GlobalPlayerState>,
+) {
+    for (entity, fragile, broken) in fragile_query.iter() {
+        if fragile.pushes_left <= 0 && broken.is_none() {
+            commands
+                .entity(entity)
+                .remove::<(Movable, Block, Pushable, Pullable, Grabbed)>()
+                .insert((Broken, Visibility::Hidden));
+            for player in grabbing_query.iter() {
+                commands.entity(player).remove::<Grabbing>();
+            }
+            global_player_state.grabbing = false;
+        } else if fragile.pushes_left > 0 && broken.is_some() {
+            commands.entity(entity).remove::<Broken>().insert((
+                Movable::default(),
+                Block,
+                Pushable,
+                Pullable,
+                Visibility::Inherited,
+            ));
+        }
+    }
+}
@@ -1,26 +1,34 @@
 // This example has a tutorial in the bevy_ecs_ldtk book associated with it:
 // <https://trouv.github.io/bevy_ecs_ldtk/latest/tutorials/tile-based-game/index.html>
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 use bevy_tweening::{lens::TransformPositionLens, Animator, EaseFunction, Tween, TweenCompleted};
-use std::{collections::HashSet, time::Duration};
-use bevy_kira_audio::prelude::*;
 
-use crate::{player::{PlayerBundle, handle_move_player, Movable, handle_move_player_event, update_moveable_neighbors}, GameState};
+use crate::error::GameError;
+use crate::gravity::Ladder;
+use crate::loading::LevelAssets;
+use crate::pause::{resuming, LeaveLevel};
+use crate::save::SaveData;
+use crate::terrain::CrumblingFloor;
+use std::collections::HashMap;
+
+use crate::{player::{PlayerBundle, handle_move_player, Movable, handle_move_player_event}, GameState};
 
 pub struct TilemapPlugin;
 
 impl Plugin for TilemapPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(OnEnter(GameState::Playing), setup)
-            // .add_systems(OnEnter(GameState::Playing), start_background_audio)
+            .add_systems(OnEnter(GameState::Playing), setup.run_if(not(resuming)))
+            .add_systems(LeaveLevel, cleanup_world)
             .insert_resource(LevelSelection::index(0))
             .insert_resource(LdtkSettings {
                 level_spawn_behavior: LevelSpawnBehavior::UseWorldTranslation {
                     load_level_neighbors: false,
                 },
-                set_clear_color: SetClearColor::FromLevelBackground,
+                // the camera letterboxes levels, their background is drawn as a sprite
+                set_clear_color: SetClearColor::No,
                 ..Default::default()
             })
             .register_ldtk_entity::<PlayerBundle>("Player")
@@ -43,19 +51,31 @@ impl Plugin for TilemapPlugin {
                 .run_if(in_state(GameState::Playing)),
             )
             .register_ldtk_int_cell::<WallBundle>(1)
-            .init_resource::<LevelWalls>();
+            .init_resource::<WorldWalls>();
     }
 }
 
-pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn setup(
+    mut commands: Commands,
+    level_assets: Res<LevelAssets>,
+    level_selection: Res<LevelSelection>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+) {
     commands.spawn(LdtkWorldBundle {
-        ldtk_handle: asset_server.load("tile-based-game.ldtk"),
+        ldtk_handle: level_assets.project_for(&level_selection, &ldtk_project_assets),
         ..Default::default()
     });
 }
 
-fn start_background_audio(asset_server: Res<AssetServer>, audio: Res<Audio>) {
-    audio.play(asset_server.load("Bemuse.ogg")).looped();
+pub fn cleanup_world(
+    mut commands: Commands,
+    worlds: Query<Entity, With<Handle<LdtkProject>>>,
+    mut world_walls: ResMut<WorldWalls>,
+) {
+    world_walls.clear();
+    for entity in worlds.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 }
 
 #[derive(Default, Component)]
@@ -99,36 +119,210 @@ pub struct BlockBundle {
     grid_coords: GridCoords,
 }
 
-#[derive(Default, Resource)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tile {
+    #[default]
+    Floor,
+    Wall,
+    CrumblingFloor,
+    Pit,
+    ClosedDoor,
+    Ladder,
+}
+
+/// Tile grid of one level, addressed with the level's own grid coords like the
+/// entities in it. `origin` is where the level's first cell sits in world-space
+/// grid coords.
+#[derive(Default, Clone)]
 pub struct LevelWalls {
-    wall_locations: HashSet<GridCoords>,
+    tiles: Vec<Tile>,
+    origin: GridCoords,
     level_width: i32,
     level_height: i32,
 }
 
 impl LevelWalls {
-    pub fn in_wall(&self, grid_coords: &GridCoords) -> bool {
-        grid_coords.x < 0
+    pub fn new(origin: GridCoords, level_width: i32, level_height: i32) -> Self {
+        LevelWalls {
+            tiles: vec![Tile::Floor; (level_width * level_height).max(0) as usize],
+            origin,
+            level_width,
+            level_height,
+        }
+    }
+
+    pub fn to_world(&self, grid_coords: GridCoords) -> GridCoords {
+        grid_coords + self.origin
+    }
+
+    fn from_world(&self, world_coords: GridCoords) -> GridCoords {
+        GridCoords::new(world_coords.x - self.origin.x, world_coords.y - self.origin.y)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.level_width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.level_height
+    }
+
+    fn index(&self, grid_coords: &GridCoords) -> Option<usize> {
+        if grid_coords.x < 0
             || grid_coords.y < 0
             || grid_coords.x >= self.level_width
             || grid_coords.y >= self.level_height
-            || self.wall_locations.contains(grid_coords)
+        {
+            return None;
+        }
+        Some((grid_coords.y * self.level_width + grid_coords.x) as usize)
+    }
+
+    pub fn tile(&self, grid_coords: &GridCoords) -> Option<Tile> {
+        self.index(grid_coords).map(|index| self.tiles[index])
+    }
+
+    pub fn set_tile(&mut self, grid_coords: &GridCoords, tile: Tile) {
+        if let Some(index) = self.index(grid_coords) {
+            self.tiles[index] = tile;
+        }
+    }
+
+    pub fn in_wall(&self, grid_coords: &GridCoords) -> bool {
+        is_wall(self.tile(grid_coords))
+    }
+}
+
+fn is_wall(tile: Option<Tile>) -> bool {
+    matches!(
+        tile,
+        None | Some(Tile::Wall) | Some(Tile::Pit) | Some(Tile::ClosedDoor)
+    )
+}
+
+/// Tile grids of every spawned level, keyed by level iid. Unlike the spawned
+/// int cells they can change during play, so they are part of the undo history.
+#[derive(Default, Clone, Resource)]
+pub struct WorldWalls {
+    levels: HashMap<String, LevelWalls>,
+    // stands in for levels that aren't spawned, every cell of it is a wall
+    empty: LevelWalls,
+}
+
+impl WorldWalls {
+    pub fn get(&self, level_iid: &str) -> &LevelWalls {
+        self.levels.get(level_iid).unwrap_or(&self.empty)
+    }
+
+    pub fn get_mut(&mut self, level_iid: &str) -> Option<&mut LevelWalls> {
+        self.levels.get_mut(level_iid)
+    }
+
+    pub fn insert(&mut self, level_iid: String, level_walls: LevelWalls) {
+        self.levels.insert(level_iid, level_walls);
+    }
+
+    pub fn clear(&mut self) {
+        self.levels.clear();
+    }
+
+    /// The tile at a world-space grid position, in whichever level covers it.
+    pub fn tile_at(&self, world_coords: GridCoords) -> Option<Tile> {
+        self.levels
+            .values()
+            .find_map(|level_walls| level_walls.tile(&level_walls.from_world(world_coords)))
+    }
+
+    pub fn set_tile_at(&mut self, world_coords: GridCoords, tile: Tile) {
+        if let Some((level_walls, grid_coords)) = self
+            .levels
+            .values_mut()
+            .map(|level_walls| {
+                let grid_coords = level_walls.from_world(world_coords);
+                (level_walls, grid_coords)
+            })
+            .find(|(level_walls, grid_coords)| level_walls.tile(grid_coords).is_some())
+        {
+            level_walls.set_tile(&grid_coords, tile);
+        }
+    }
+}
+
+/// Finds the level an entity belongs to by walking up to its level entity.
+#[derive(SystemParam)]
+pub struct LevelMembership<'w, 's> {
+    parent_query: Query<'w, 's, &'static Parent>,
+    level_query: Query<'w, 's, &'static LevelIid>,
+}
+
+impl<'w, 's> LevelMembership<'w, 's> {
+    pub fn level_iid(&self, entity: Entity) -> Option<&LevelIid> {
+        let mut current = entity;
+        loop {
+            if let Ok(level_iid) = self.level_query.get(current) {
+                return Some(level_iid);
+            }
+            current = self.parent_query.get(current).ok()?.get();
+        }
+    }
+}
+
+/// Walls as seen by an entity: its own level, and beyond its edges whatever
+/// neighbouring level is spawned there.
+#[derive(SystemParam)]
+pub struct EntityWalls<'w, 's> {
+    world_walls: Res<'w, WorldWalls>,
+    membership: LevelMembership<'w, 's>,
+}
+
+impl<'w, 's> EntityWalls<'w, 's> {
+    pub fn world(&self) -> &WorldWalls {
+        &self.world_walls
+    }
+
+    pub fn level_iid(&self, entity: Entity) -> Option<&LevelIid> {
+        self.membership.level_iid(entity)
+    }
+
+    /// The walls of the level the entity is in.
+    pub fn of(&self, entity: Entity) -> &LevelWalls {
+        match self.level_iid(entity) {
+            Some(level_iid) => self.world_walls.get(level_iid.get()),
+            None => &self.world_walls.empty,
+        }
+    }
+
+    pub fn tile(&self, entity: Entity, grid_coords: &GridCoords) -> Option<Tile> {
+        let level_walls = self.world_walls.get(self.level_iid(entity)?.get());
+        level_walls
+            .tile(grid_coords)
+            .or_else(|| self.world_walls.tile_at(level_walls.to_world(*grid_coords)))
+    }
+
+    pub fn in_wall(&self, entity: Entity, grid_coords: &GridCoords) -> bool {
+        is_wall(self.tile(entity, grid_coords))
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.world_walls.is_changed()
     }
 }
 
 #[derive(Default, Component)]
 pub struct IsMoving;
 
-const GRID_SIZE: i32 = 16;
+pub const GRID_SIZE: i32 = 16;
 
 pub fn translate_grid_coords_entities(
     mut commands: Commands,
     mut grid_coords_entities: Query<(Entity, &mut Transform, &GridCoords), Changed<GridCoords>>,
+    save_data: Res<SaveData>,
 ) {
+    let settings = &save_data.settings;
     for (entity, transform, grid_coords) in grid_coords_entities.iter_mut() {
         let tween = Tween::new(
-            EaseFunction::QuadraticInOut,
-            Duration::from_millis(100),
+            settings.ease(EaseFunction::QuadraticInOut),
+            settings.move_duration(),
             TransformPositionLens {
                 start: transform.translation,
                 end: Vec3::new(
@@ -180,30 +374,55 @@ fn move_complete_listener(
 }
 
 pub fn cache_wall_locations(
-    mut level_walls: ResMut<LevelWalls>,
+    mut world_walls: ResMut<WorldWalls>,
     mut level_events: EventReader<LevelEvent>,
-    walls: Query<&GridCoords, With<Wall>>,
+    walls: Query<(Entity, &GridCoords), With<Wall>>,
+    crumbling_floors: Query<(Entity, &GridCoords), With<CrumblingFloor>>,
+    ladders: Query<(Entity, &GridCoords), With<Ladder>>,
+    membership: LevelMembership,
     ldtk_project_entities: Query<&Handle<LdtkProject>>,
     ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut ev_game_error: EventWriter<GameError>,
 ) {
     for level_event in level_events.iter() {
-        if let LevelEvent::Spawned(level_iid) = level_event {
-            let ldtk_project = ldtk_project_assets
-                .get(ldtk_project_entities.single())
-                .expect("LdtkProject should be loaded when level is spawned");
-            let level = ldtk_project
-                .get_raw_level_by_iid(level_iid.get())
-                .expect("spawned level should exist in project");
-
-            let wall_locations = walls.iter().copied().collect();
-
-            let new_level_walls = LevelWalls {
-                wall_locations,
-                level_width: level.px_wid / GRID_SIZE,
-                level_height: level.px_hei / GRID_SIZE,
-            };
-
-            *level_walls = new_level_walls;
+        match level_event {
+            LevelEvent::Spawned(level_iid) => {
+                let Some(ldtk_project) = ldtk_project_entities
+                    .iter()
+                    .find_map(|handle| ldtk_project_assets.get(handle))
+                else {
+                    ev_game_error.send(GameError::ProjectNotLoaded(level_iid.get().clone()));
+                    continue;
+                };
+                let Some(level) = ldtk_project.get_raw_level_by_iid(level_iid.get()) else {
+                    ev_game_error.send(GameError::LevelNotFound(level_iid.get().clone()));
+                    continue;
+                };
+
+                // LDtk's world y points down, so the bottom row is the level's origin
+                let origin = GridCoords::new(
+                    level.world_x / GRID_SIZE,
+                    -(level.world_y + level.px_hei) / GRID_SIZE,
+                );
+                let mut level_walls =
+                    LevelWalls::new(origin, level.px_wid / GRID_SIZE, level.px_hei / GRID_SIZE);
+                let in_level = |entity: &Entity| membership.level_iid(*entity) == Some(level_iid);
+                for (_, grid_coords) in walls.iter().filter(|(entity, _)| in_level(entity)) {
+                    level_walls.set_tile(grid_coords, Tile::Wall);
+                }
+                for (_, grid_coords) in crumbling_floors.iter().filter(|(entity, _)| in_level(entity)) {
+                    level_walls.set_tile(grid_coords, Tile::CrumblingFloor);
+                }
+                for (_, grid_coords) in ladders.iter().filter(|(entity, _)| in_level(entity)) {
+                    level_walls.set_tile(grid_coords, Tile::Ladder);
+                }
+
+                world_walls.insert(level_iid.get().clone(), level_walls);
+            }
+            LevelEvent::Despawned(level_iid) => {
+                world_walls.levels.remove(level_iid.get());
+            }
+            _ => {}
         }
     }
 }